@@ -0,0 +1,201 @@
+use std::path::PathBuf;
+use linked_html_compiler::config::Output;
+use linked_html_compiler::site::Site;
+
+pub const USAGE: &str = "\
+Usage: linked_html_compiler [OPTIONS] [INPUT]...
+       linked_html_compiler build [OPTIONS] [SRC_DIR]
+       linked_html_compiler serve [OPTIONS] [SRC_DIR]
+
+Compiles each INPUT page into a single HTML file.
+If no INPUT is given, the path is read from the standard input.
+`build` compiles every page below SRC_DIR [default: .] into --out-dir [default: dist].
+`serve` builds like `build`, then watches the sources and serves --out-dir over HTTP,
+reloading the browser after each rebuild.
+
+Options:
+  -o, --output <PATH>  Write the compiled page to PATH (one INPUT only)
+      --out-dir <DIR>  Write each page into DIR, mirroring its path below --root
+      --root <DIR>     Source root mirrored by --out-dir, and against which links
+                       starting with / are resolved [default: SRC_DIR, or . for
+                       --out-dir and the directory of each INPUT for links]
+      --name <PATTERN> Name each output file by PATTERN, where {stem}, {ext}
+                       and {name} are parts of the input file name
+                       [default: out.html, or {name} with --out-dir]
+      --pages <GLOB>   Pages to build below SRC_DIR [default: **/*.html]
+      --partial-prefix <PREFIX>
+                       Skip files and directories starting with PREFIX when
+                       building [default: _]
+  -D, --define <KEY=VALUE>
+                       Set the variable KEY to VALUE on every page; may be repeated
+      --max-include-depth <N>
+                       Fail when includes nest deeper than N [default: 32]
+  -w, --watch          Keep running and recompile pages when they or the files
+                       they include change
+      --host <HOST>    Address to serve on [default: 127.0.0.1]
+      --port <PORT>    Port to serve on [default: 8000]
+  -q, --quiet          Print errors only
+  -v, --verbose        Print the progress of each page
+  -h, --help           Print this help and exit
+  -V, --version        Print the version and exit";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+    Quiet,
+    Normal,
+    Verbose,
+}
+
+#[derive(Debug)]
+pub enum Mode {
+    Compile(Vec<String>),
+    Build(Site),
+}
+
+#[derive(Debug)]
+pub struct Args {
+    pub mode: Mode,
+    pub output: Output,
+    pub verbosity: Verbosity,
+    pub watch: bool,
+    pub max_include_depth: Option<usize>,
+    /// Build-wide variables given by `--define`.
+    pub defines: Vec<(String, String)>,
+    /// The directory that links starting with `/` are resolved against.
+    pub root: Option<PathBuf>,
+    /// The address to serve the output directory on.
+    pub serve: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum Command {
+    Compile(Box<Args>),
+    Help,
+    Version,
+}
+
+pub fn parse<I: Iterator<Item = String>>(args: I) -> Result<Command, String> {
+    let mut args = args.peekable();
+    let subcommand = args.next_if(|arg| arg == "build" || arg == "serve");
+    let serve = subcommand.as_deref() == Some("serve");
+    let mut inputs = Vec::new();
+    let mut verbosity = Verbosity::Normal;
+    let mut watch = false;
+    let mut max_include_depth = None;
+    let mut defines = Vec::new();
+    let mut file = None;
+    let mut dir = None;
+    let mut root = None;
+    let mut name = None;
+    let mut pages = None;
+    let mut partial_prefix = None;
+    let mut host = None;
+    let mut port = None;
+    while let Some(arg) = args.next() {
+        let (key, inline_value) = match arg.split_once('=') {
+            Some((key, value)) if arg.starts_with("--") => (key, Some(value.to_string())),
+            _ => (arg.as_str(), None),
+        };
+        match key {
+            "-h" | "--help" => return Ok(Command::Help),
+            "-V" | "--version" => return Ok(Command::Version),
+            "-q" | "--quiet" => verbosity = Verbosity::Quiet,
+            "-v" | "--verbose" => verbosity = Verbosity::Verbose,
+            "-w" | "--watch" => watch = true,
+            "--max-include-depth" => {
+                let value = value_of(key, inline_value, &mut args)?;
+                max_include_depth = Some(value.parse::<usize>()
+                    .map_err(|_| format!("Invalid include depth: {}", value))?);
+            }
+            "-D" | "--define" => {
+                let value = value_of(key, inline_value, &mut args)?;
+                let (key, value) = value.split_once('=')
+                    .filter(|(key, _)| !key.is_empty())
+                    .ok_or_else(|| format!("Invalid definition, expected KEY=VALUE: {}", value))?;
+                defines.push((key.to_string(), value.to_string()));
+            }
+            "-o" | "--output" => file = Some(PathBuf::from(value_of(key, inline_value, &mut args)?)),
+            "--out-dir" => dir = Some(PathBuf::from(value_of(key, inline_value, &mut args)?)),
+            "--root" => root = Some(PathBuf::from(value_of(key, inline_value, &mut args)?)),
+            "--name" => name = Some(value_of(key, inline_value, &mut args)?),
+            "--pages" => pages = Some(value_of(key, inline_value, &mut args)?),
+            "--partial-prefix" => partial_prefix = Some(value_of(key, inline_value, &mut args)?),
+            "--host" => host = Some(value_of(key, inline_value, &mut args)?),
+            "--port" => {
+                let value = value_of(key, inline_value, &mut args)?;
+                port = Some(value.parse::<u16>().map_err(|_| format!("Invalid port: {}", value))?);
+            }
+            "--" => {
+                inputs.extend(args.by_ref());
+            }
+            _ if key.starts_with('-') && key.len() > 1 => {
+                return Err(format!("Unknown option: {}", key))
+            }
+            _ => inputs.push(arg),
+        }
+    }
+    if !serve && (host.is_some() || port.is_some()) {
+        return Err(String::from("--host and --port are only used by serve"))
+    }
+    if let Some(subcommand) = subcommand {
+        if file.is_some() {
+            return Err(format!("{} writes into --out-dir and cannot use --output", subcommand))
+        }
+        if inputs.len() > 1 {
+            return Err(format!("{} accepts only one source directory", subcommand))
+        }
+        let mut site = Site::new(inputs.pop().unwrap_or_else(|| String::from(".")));
+        site.pages = pages.unwrap_or(site.pages);
+        site.partial_prefix = partial_prefix.unwrap_or(site.partial_prefix);
+        let root = root.unwrap_or_else(|| site.root.clone());
+        let output = Output::Dir {
+            root: root.clone(),
+            dir: dir.unwrap_or_else(|| PathBuf::from("dist")),
+            name: name.unwrap_or_else(|| String::from("{name}")),
+        };
+        let serve = serve.then(|| format!("{}:{}", host.unwrap_or_else(|| String::from("127.0.0.1")),
+                                          port.unwrap_or(8000)));
+        return Ok(Command::Compile(Box::new(Args {
+            mode: Mode::Build(site),
+            output,
+            verbosity,
+            watch: watch || serve.is_some(),
+            max_include_depth,
+            defines,
+            root: Some(root),
+            serve,
+        })))
+    }
+    if pages.is_some() || partial_prefix.is_some() {
+        return Err(String::from("--pages and --partial-prefix are only used by build and serve"))
+    }
+    let output = match (file, dir) {
+        (Some(_), Some(_)) => return Err(String::from("--output cannot be used with --out-dir")),
+        (Some(_), None) if name.is_some() => return Err(String::from("--output cannot be used with --name")),
+        (Some(_), None) if inputs.len() > 1 => return Err(String::from("--output accepts only one input")),
+        (Some(file), None) => Output::File(file),
+        (None, Some(dir)) => Output::Dir {
+            root: root.clone().unwrap_or_else(|| PathBuf::from(".")),
+            dir,
+            name: name.unwrap_or_else(|| String::from("{name}")),
+        },
+        (None, None) => name.map_or_else(Output::default, Output::Pattern),
+    };
+    Ok(Command::Compile(Box::new(Args {
+        mode: Mode::Compile(inputs),
+        output,
+        verbosity,
+        watch,
+        max_include_depth,
+        defines,
+        root,
+        serve: None,
+    })))
+}
+
+fn value_of<I: Iterator<Item = String>>(key: &str, inline_value: Option<String>, args: &mut I) -> Result<String, String> {
+    if let Some(value) = inline_value {
+        return Ok(value)
+    }
+    args.next().ok_or_else(|| format!("Option {} requires a value", key))
+}
@@ -1,50 +1,389 @@
+use std::fs;
 use std::fs::{File, OpenOptions};
 use std::io;
 use std::io::{BufReader, BufWriter, Read, Write};
 use std::path::{Path, PathBuf};
 use std::rc::Rc;
+use serde_json::Value;
+use crate::data;
+use crate::error::{Error, ErrorKind, Result};
+use crate::parse::doc::Doc;
+use crate::parse::span::Span;
+use crate::util;
 use crate::util::{LazyClosure, VecDict};
 
+#[derive(Debug, Clone)]
+pub enum Output {
+    /// Writes to this exact path.
+    File(PathBuf),
+    /// Mirrors the input's path below `root` into `dir`, naming the file by the pattern `name`.
+    Dir { root: PathBuf, dir: PathBuf, name: String },
+    /// Writes next to the input, naming the file by the pattern.
+    /// `{stem}`, `{ext}` and `{name}` are replaced with the parts of the input file name.
+    Pattern(String),
+}
+
+impl Output {
+    pub fn resolve(&self, input: &Path) -> PathBuf {
+        match self {
+            Self::File(path) => path.clone(),
+            Self::Dir { root, dir, name } => {
+                let relative = relative_to(input, root);
+                let parent = relative.parent().unwrap_or(Path::new(""));
+                dir.join(parent).join(expand_name(name, input))
+            }
+            Self::Pattern(name) => {
+                let parent = input.parent().unwrap_or(Path::new(""));
+                parent.join(expand_name(name, input))
+            }
+        }
+    }
+}
+
+impl Default for Output {
+    fn default() -> Self {
+        Self::Pattern(String::from("out.html"))
+    }
+}
+
+fn relative_to(input: &Path, root: &Path) -> PathBuf {
+    if let Ok(relative) = util::normalize_path(input).strip_prefix(util::normalize_path(root)) {
+        return relative.to_path_buf()
+    }
+    if let (Ok(input), Ok(root)) = (input.canonicalize(), root.canonicalize()) {
+        if let Ok(relative) = input.strip_prefix(root) {
+            return relative.to_path_buf()
+        }
+    }
+    PathBuf::from(input.file_name().unwrap_or_default())
+}
+
+fn expand_name(pattern: &str, input: &Path) -> String {
+    let part = |s: Option<&std::ffi::OsStr>| s.unwrap_or_default().to_string_lossy().into_owned();
+    pattern.replace("{stem}", &part(input.file_stem()))
+        .replace("{ext}", &part(input.extension()))
+        .replace("{name}", &part(input.file_name()))
+}
+
 pub struct Config {
+    page: PathBuf,
     workspace: PathBuf,
-    out: LazyClosure<PathBuf, BufWriter<File>>,
+    root: Option<PathBuf>,
+    output: Output,
+    out_path: PathBuf,
+    out: LazyClosure<PathBuf, io::Result<BufWriter<File>>>,
     src: VecDict<PathBuf, Rc<String>>,
+    deps: Vec<PathBuf>,
+    /// The files being expanded, with the part taken of each, e.g. `select="#card"`, and their sources.
+    expanding: Vec<(PathBuf, Option<String>, Rc<String>)>,
+    max_include_depth: usize,
+    frames: Vec<IncludeFrame>,
+    blocks: Vec<VecDict<String, Doc>>,
+    inherited_blocks: Option<VecDict<String, Doc>>,
+    hoisted: VecDict<String, Doc>,
+    defines: VecDict<String, String>,
+    /// Variables set by `<!--?set-->`: one scope for the page and one more for each include being expanded.
+    scopes: Vec<VecDict<String, String>>,
+    /// Whether the custom tag being expanded is inside a `<script>` or `<style>` element.
+    in_script: bool,
+    /// Problems found so far that do not stop the compilation, for the caller to report.
+    warnings: Vec<Error>,
+}
+
+/// What an include passes to the partial it expands.
+#[derive(Default)]
+struct IncludeFrame {
+    props: VecDict<String, String>,
+    slots: VecDict<String, Doc>,
 }
 
 impl Config {
-    pub fn new(input: String) -> (Self, io::Result<Rc<String>>) {
-        let workspace = Path::new(&input).parent()
-            .expect("[FATAL] Failed to open working directory")
-            .to_path_buf();
-        let out = LazyClosure::new(|path| {
-            BufWriter::new(OpenOptions::new()
-            .create(true).truncate(true).write(true)
-            .open(path)
-            .expect("[FATAL] Failed to open the output file"))
-        }, workspace.join("out.html"));
-        let mut cfg = Self {
-            workspace,
-            out,
+    pub fn new(input: String, output: Output) -> (Self, Result<Rc<String>>) {
+        let mut cfg = Self::with_output(output);
+        let source = cfg.open_page(PathBuf::from(input));
+        (cfg, source)
+    }
+    /// Creates a config without a page, so that several pages can share its source cache.
+    pub fn with_output(output: Output) -> Self {
+        Self {
+            page: PathBuf::new(),
+            workspace: PathBuf::new(),
+            root: None,
+            out_path: output.resolve(Path::new("")),
+            out: Self::open_lazily(PathBuf::new()),
+            output,
             src: VecDict::new(),
+            deps: Vec::new(),
+            expanding: Vec::new(),
+            max_include_depth: 32,
+            frames: Vec::new(),
+            blocks: Vec::new(),
+            inherited_blocks: None,
+            hoisted: VecDict::new(),
+            defines: VecDict::new(),
+            scopes: vec![VecDict::new()],
+            in_script: false,
+            warnings: Vec::new(),
+        }
+    }
+    /// Switches to the next page to compile, keeping the files read so far.
+    pub fn open_page(&mut self, page: PathBuf) -> Result<Rc<String>> {
+        self.workspace = match page.parent() {
+            Some(parent) => parent.to_path_buf(),
+            None => return Err(ErrorKind::InvalidPage(page).into()),
         };
-        let source = cfg.read_absolute(PathBuf::from(input));
-        (cfg, source)
+        self.page = page.clone();
+        self.deps.clear();
+        self.expanding.clear();
+        self.frames.clear();
+        self.blocks.clear();
+        self.inherited_blocks = None;
+        self.hoisted = VecDict::new();
+        self.scopes = vec![VecDict::new()];
+        self.in_script = false;
+        self.set_output(self.output.clone());
+        self.read(&page)
+    }
+    pub fn page(&self) -> &Path {
+        &self.page
+    }
+    fn open_lazily(out_path: PathBuf) -> LazyClosure<PathBuf, io::Result<BufWriter<File>>> {
+        LazyClosure::new(|path: PathBuf| {
+            if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+                fs::create_dir_all(parent)?;
+            }
+            Ok(BufWriter::new(OpenOptions::new()
+            .create(true).truncate(true).write(true)
+            .open(path)?))
+        }, out_path)
+    }
+    fn out(&mut self) -> Result<&mut BufWriter<File>> {
+        match self.out.get_mut() {
+            Ok(out) => Ok(out),
+            Err(e) => Err(Error::io(io::Error::new(e.kind(), e.to_string()), &self.out_path)),
+        }
+    }
+    pub fn output(&self) -> &Output {
+        &self.output
+    }
+    pub fn set_output(&mut self, output: Output) {
+        self.out_path = output.resolve(&self.page);
+        self.out = Self::open_lazily(self.out_path.clone());
+        self.output = output;
+    }
+    pub fn out_path(&self) -> &Path {
+        &self.out_path
+    }
+    pub fn write_all<S: AsRef<str>>(&mut self, text: S) -> Result<()> {
+        self.out()?.write_all(text.as_ref().as_bytes())
+            .map_err(|e| Error::io(e, &self.out_path))
+    }
+    /// Lists the files read while compiling the current page, starting with the page itself.
+    pub fn dependencies(&self) -> &[PathBuf] {
+        &self.deps
+    }
+    /// Drops a file from the source cache, so that the next read loads it from disk again.
+    pub fn invalidate(&mut self, path: &Path) {
+        self.src.remove(&util::normalize_path(path));
+    }
+    pub fn max_include_depth(&self) -> usize {
+        self.max_include_depth
     }
-    pub fn write_all<S: AsRef<str>>(&mut self, text: S) {
-        self.out.get_mut().write_all(text.as_ref().as_bytes())
-                .expect("[FATAL] Failed to write text to the output file.");
+    pub fn set_max_include_depth(&mut self, depth: usize) {
+        self.max_include_depth = depth;
     }
-    pub fn read_relative<P: AsRef<Path>>(&mut self, path: P) -> io::Result<Rc<String>> {
-        self.read_absolute(self.workspace.join(path))
+    /// Marks `part` of `file`, or all of it, as being expanded, failing if it is already being expanded
+    /// or if the includes would nest too deep. Different parts of a file may include each other.
+    pub fn enter(&mut self, file: &Path, part: Option<&str>, source: Rc<String>) -> Result<()> {
+        let file = util::normalize_path(file);
+        let part = part.map(String::from);
+        if let Some(idx) = self.expanding.iter().position(|(f, p, _)| *f == file && *p == part) {
+            let describe = |file: &Path, part: &Option<String>| match part {
+                Some(part) => format!("{} {}", file.display(), part),
+                None => file.display().to_string(),
+            };
+            let mut cycle: Vec<_> = self.expanding[idx..].iter()
+                .map(|(f, p, _)| describe(f, p))
+                .collect();
+            cycle.push(describe(&file, &part));
+            return Err(ErrorKind::CircularInclude(cycle).into())
+        }
+        if self.expanding.len() > self.max_include_depth {
+            return Err(ErrorKind::IncludeTooDeep(self.max_include_depth).into())
+        }
+        self.expanding.push((file, part, source));
+        Ok(())
+    }
+    /// The file being expanded and its source.
+    pub fn current(&self) -> Option<(&Path, &Rc<String>)> {
+        self.expanding.last().map(|(file, _, source)| (file.as_path(), source))
+    }
+    /// Marks the file entered last as expanded.
+    pub fn leave(&mut self) {
+        self.expanding.pop();
+    }
+    /// Makes the attributes and the slot contents of an include available to the partial it expands.
+    pub fn push_include(&mut self, props: VecDict<String, String>, slots: VecDict<String, Doc>) {
+        self.frames.push(IncludeFrame {
+            props,
+            slots,
+        });
+        self.push_scope();
+    }
+    pub fn pop_include(&mut self) {
+        self.frames.pop();
+        self.pop_scope();
+    }
+    /// Starts a scope of variables, e.g. for an iteration of a loop.
+    pub fn push_scope(&mut self) {
+        self.scopes.push(VecDict::new());
+    }
+    pub fn pop_scope(&mut self) {
+        if self.scopes.len() > 1 {
+            self.scopes.pop();
+        }
+    }
+    /// Looks up a parameter passed to the partial being expanded.
+    pub fn prop(&self, key: &str) -> Option<&str> {
+        self.frames.last()?.props.get(key).map(String::as_str)
+    }
+    /// Sets a parameter of the partial being expanded unless the include passed it.
+    pub fn set_default_prop(&mut self, key: String, value: String) {
+        if self.frames.is_empty() {
+            self.frames.push(IncludeFrame::default());
+        }
+        self.frames.last_mut().unwrap().props.push_unique(key, value);
+    }
+    /// Looks up the content passed for a slot of the partial being expanded. The default slot is named `""`.
+    pub fn slot(&self, name: &str) -> Option<&Doc> {
+        self.frames.last()?.slots.get(name)
+    }
+    /// Hands the blocks of a page to the layout it extends, which is expanded next.
+    pub fn inherit_blocks(&mut self, blocks: VecDict<String, Doc>) {
+        self.inherited_blocks = Some(blocks);
+    }
+    /// Takes the blocks handed to the file about to be expanded, if it is a layout.
+    pub fn take_inherited_blocks(&mut self) -> Option<VecDict<String, Doc>> {
+        self.inherited_blocks.take()
+    }
+    /// Makes block overrides visible to `<!--?block-->` while a layout is expanded.
+    pub fn push_blocks(&mut self, blocks: VecDict<String, Doc>) {
+        self.blocks.push(blocks);
+    }
+    pub fn pop_blocks(&mut self) {
+        self.blocks.pop();
+    }
+    /// Looks up the content a page gave for a block of the layout being expanded.
+    pub fn block(&self, name: &str) -> Option<&Doc> {
+        self.blocks.last()?.get(name)
     }
-    fn read_absolute(&mut self, path: PathBuf) -> io::Result<Rc<String>> {
-        if self.src.get(&path) == None {
-            let mut file = BufReader::new(
-                OpenOptions::new().read(true).open(&path)?);
-            let mut buf = String::new();
-            file.read_to_string(&mut buf)?;
-            self.src.push_unique(path.clone(), Rc::new(buf));
+    /// Defines a build-wide variable, e.g. from `--define`, seen by every page.
+    pub fn define(&mut self, key: String, value: String) {
+        self.defines.remove(&key);
+        self.defines.push_unique(key, value);
+    }
+    /// Sets a variable of the file being expanded. Variables set in a partial stay in the partial,
+    /// while those set in a page are also seen by the layout it extends.
+    pub fn set_var(&mut self, key: String, value: String) {
+        let scope = self.scopes.last_mut().expect("the page scope is never popped");
+        scope.remove(&key);
+        scope.push_unique(key, value);
+    }
+    /// Looks up a variable, from the file being expanded out through the files that include it,
+    /// then among the build-wide variables.
+    pub fn var(&self, key: &str) -> Option<&str> {
+        self.scopes.iter().rev()
+            .find_map(|scope| scope.get(key))
+            .or_else(|| self.defines.get(key))
+            .map(String::as_str)
+    }
+    /// Marks whether the custom tag being expanded is inside a `<script>` or `<style>` element,
+    /// whose text is not HTML-escaped. Returns the previous mark.
+    pub fn set_in_script(&mut self, in_script: bool) -> bool {
+        std::mem::replace(&mut self.in_script, in_script)
+    }
+    pub fn in_script(&self) -> bool {
+        self.in_script
+    }
+    /// Records a problem that does not stop the compilation.
+    pub fn warn(&mut self, warning: Error) {
+        self.warnings.push(warning);
+    }
+    /// Takes the warnings recorded so far.
+    pub fn take_warnings(&mut self) -> Vec<Error> {
+        std::mem::take(&mut self.warnings)
+    }
+    /// Keeps a `<head>` child of an included file for the head of the page, unless one with the same key is kept.
+    pub fn hoist(&mut self, key: String, item: Doc) {
+        self.hoisted.push_unique(key, item);
+    }
+    /// Takes the `<head>` children kept so far, in the order they were first seen.
+    pub fn take_hoisted(&mut self) -> VecDict<String, Doc> {
+        std::mem::take(&mut self.hoisted)
+    }
+    pub fn flush(&mut self) -> Result<()> {
+        self.out()?.flush()
+            .map_err(|e| Error::io(e, &self.out_path))
+    }
+    /// The directory that links starting with `/` are resolved against.
+    /// Defaults to the directory of the current page.
+    pub fn root(&self) -> &Path {
+        self.root.as_deref().unwrap_or(&self.workspace)
+    }
+    pub fn set_root(&mut self, root: Option<PathBuf>) {
+        self.root = root;
+    }
+    /// Resolves a link found in the file being expanded: relative to that file,
+    /// or relative to the root if the link starts with `/`.
+    pub fn resolve_relative<P: AsRef<Path>>(&self, path: P) -> PathBuf {
+        let path = path.as_ref();
+        if let Ok(anchored) = path.strip_prefix("/") {
+            return util::normalize_path(&self.root().join(anchored))
+        }
+        let base = self.expanding.last()
+            .and_then(|(file, _, _)| file.parent())
+            .unwrap_or(&self.workspace);
+        util::normalize_path(&base.join(path))
+    }
+    pub fn read_relative<P: AsRef<Path>>(&mut self, path: P) -> Result<Rc<String>> {
+        self.read(&self.resolve_relative(path))
+    }
+    /// Reads a file through the source cache and records it as a dependency of the current page.
+    /// Bytes that are not UTF-8 are replaced with a warning.
+    pub fn read(&mut self, path: &Path) -> Result<Rc<String>> {
+        let path = util::normalize_path(path);
+        if !self.deps.contains(&path) {
+            self.deps.push(path.clone());
+        }
+        if !self.src.contains(&path) {
+            let buf = Self::read_to_end(&path)
+                .map_err(|e| Error::io(e, &path))?;
+            let text = match String::from_utf8(buf) {
+                Ok(text) => text,
+                Err(e) => {
+                    let valid = e.utf8_error().valid_up_to();
+                    let text = String::from_utf8_lossy(e.as_bytes()).into_owned();
+                    self.warn(Error::new(ErrorKind::InvalidUtf8)
+                        .at(Span::new(valid, valid + 1))
+                        .in_source(&path, &text));
+                    text
+                }
+            };
+            self.src.push_unique(path.clone(), Rc::new(text));
         }
         Ok(Rc::clone(self.src.get(&path).unwrap()))
     }
+    /// Reads a JSON, TOML or CSV file through the source cache.
+    pub fn read_data(&mut self, path: &Path) -> Result<Value> {
+        let source = self.read(path)?;
+        data::parse(path, &source)
+            .map_err(|e| Error::new(ErrorKind::InvalidData(e)).in_file(path))
+    }
+    fn read_to_end(path: &Path) -> io::Result<Vec<u8>> {
+        let mut file = BufReader::new(
+            OpenOptions::new().read(true).open(path)?);
+        let mut buf = Vec::new();
+        file.read_to_end(&mut buf)?;
+        Ok(buf)
+    }
 }
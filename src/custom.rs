@@ -1,18 +1,81 @@
+pub(crate) mod extends;
+pub(crate) mod head;
+mod cond;
 mod include;
+mod iter;
+mod meta;
+mod prop;
+mod slot;
+pub(crate) mod var;
 
 use once_cell::sync::Lazy;
 use crate::config::Config;
+use crate::error::{Error, ErrorKind, Result};
 use crate::parse::doc::Doc;
 use crate::parse::tag::Tag;
+use crate::parse::tree::Tree;
+use crate::util;
 use crate::util::VecDict;
 
-static CUSTOM_TAGS: Lazy<VecDict<&'static str, fn(Tag, &mut Config) -> Option<Doc>>> = Lazy::new(|| {
-    let mut dict: VecDict<&'static str, fn(Tag, &mut Config) -> Option<Doc>> = VecDict::new();
+/// Expands a custom tag, given the body enclosed by its closing tag if it has one.
+type CustomTagFn = fn(Tag, Option<Doc>, &mut Config) -> Result<Doc>;
+
+static CUSTOM_TAGS: Lazy<VecDict<&'static str, CustomTagFn>> = Lazy::new(|| {
+    let mut dict: VecDict<&'static str, CustomTagFn> = VecDict::new();
     dict.push_unique("include", include::run);
+    dict.push_unique("prop", prop::run);
+    dict.push_unique("props", prop::run_defaults);
+    dict.push_unique("slot", slot::run);
+    dict.push_unique("block", extends::run_block);
+    dict.push_unique("if", cond::run);
+    dict.push_unique("for", iter::run);
+    dict.push_unique("set", var::run_set);
+    dict.push_unique("var", var::run);
     dict
 });
 
-pub fn run(tag: Tag, config: &mut Config) -> Option<Doc> {
-    let func = CUSTOM_TAGS.get(&tag.tag())?;
-    func(tag, config)
+/// Rewrites the tree of a compiled page.
+type TransformFn = fn(&mut Tree, &mut Config) -> Result<()>;
+
+/// Transforms run over each page after its custom tags are expanded, in order.
+static TRANSFORMS: [TransformFn; 1] = [head::merge];
+
+pub fn transform(tree: &mut Tree, config: &mut Config) -> Result<()> {
+    for func in TRANSFORMS {
+        func(tree, config)?;
+    }
+    Ok(())
+}
+
+/// Removes the attribute `key` of a custom tag and returns its unquoted value, failing if it is missing.
+pub(crate) fn required(tag: &mut Tag, key: &str) -> Result<String> {
+    match tag.consume(key) {
+        Some(value) => Ok(util::unquote(&value).to_string()),
+        None => Err(missing(tag, key)),
+    }
+}
+
+/// The error for a custom tag without the attribute `key`, which it requires.
+pub(crate) fn missing(tag: &Tag, key: &str) -> Error {
+    Error::new(ErrorKind::MissingAttribute {
+        tag: tag.tag().to_string(),
+        key: key.to_string(),
+    }).at(tag.span())
+}
+
+/// Fails on the first attribute of a custom tag left after it consumed those it takes.
+pub(crate) fn reject_unknown(tag: &Tag) -> Result<()> {
+    match tag.attributes().next() {
+        Some((key, _)) => Err(Error::new(ErrorKind::UnknownAttribute {
+            tag: tag.tag().to_string(),
+            key: key.to_string(),
+        }).at(tag.attribute_span(key).unwrap_or(tag.span()))),
+        None => Ok(()),
+    }
+}
+
+pub fn run(tag: Tag, body: Option<Doc>, config: &mut Config) -> Result<Doc> {
+    let Some(func) = CUSTOM_TAGS.get(&tag.tag())
+        else { return Err(ErrorKind::UnknownCustomTag(tag.tag().to_string()).into()) };
+    func(tag, body, config)
 }
@@ -0,0 +1,51 @@
+use crate::config::Config;
+use crate::custom;
+use crate::error::{Error, ErrorKind, Result};
+use crate::parse;
+use crate::parse::doc::Doc;
+use crate::parse::tag::Tag;
+use crate::util;
+
+/// `<!--?if var="env" eq="prod"-->...<!--?else-->...<!--?/if-->` expands the part before `<!--?else-->`
+/// if the condition holds, and the part after it otherwise. Only the branch taken is compiled.
+/// Conditions:
+/// - `var="name"` holds if the variable is set; with `eq="value"` or `ne="value"`, if it is or is not that value.
+/// - `exists="path"` holds if the file exists, resolved like the link of an include.
+pub fn run(mut tag: Tag, body: Option<Doc>, cfg: &mut Config) -> Result<Doc> {
+    let Some(mut then) = body
+        else { return Err(Error::new(ErrorKind::UnmatchedTag(String::from("!--?if--"))).at(tag.span())) };
+    let otherwise = match then.position_custom("else") {
+        Some(idx) => {
+            let mut otherwise = then.split_off(idx);
+            otherwise.remove(0);
+            if let Some(extra) = otherwise.position_custom("else") {
+                let span = otherwise.span(extra);
+                return Err(Error::new(ErrorKind::DuplicateTag(String::from("!--?else--"))).at(span))
+            }
+            otherwise
+        }
+        None => Doc::empty(),
+    };
+    let branch = if holds(&mut tag, cfg)? { then } else { otherwise };
+    parse::expand(branch, cfg)
+}
+
+fn holds(tag: &mut Tag, cfg: &Config) -> Result<bool> {
+    let attribute = |tag: &mut Tag, key: &str| tag.consume(key).map(|v| util::unquote(&v).to_string());
+    if let Some(path) = attribute(tag, "exists") {
+        custom::reject_unknown(tag)?;
+        return Ok(cfg.resolve_relative(path).exists())
+    }
+    let name = custom::required(tag, "var")?;
+    let eq = attribute(tag, "eq");
+    let ne = attribute(tag, "ne");
+    custom::reject_unknown(tag)?;
+    let value = cfg.prop(&name).or_else(|| cfg.var(&name));
+    if let Some(expected) = eq {
+        return Ok(value == Some(expected.as_str()))
+    }
+    if let Some(expected) = ne {
+        return Ok(value != Some(expected.as_str()))
+    }
+    Ok(value.is_some())
+}
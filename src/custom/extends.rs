@@ -0,0 +1,101 @@
+use crate::config::Config;
+use crate::custom;
+use crate::custom::{meta, var};
+use crate::error::{Error, ErrorKind, Result};
+use crate::parse;
+use crate::parse::doc::Doc;
+use crate::parse::tag::Tag;
+use crate::util::VecDict;
+
+/// Expands a document parsed from the file being expanded.
+/// If it has `<!--?extends link="layout.html"-->`, the result is the whole linked layout,
+/// with the layout's `<!--?block name="..."-->` replaced by the document's blocks of the same name.
+/// The `layout` key of the front matter extends a layout the same way.
+/// Variables set outside the blocks are set before the layout is expanded;
+/// anything else in the document outside its blocks is dropped.
+pub fn expand(mut doc: Doc, inherited: Option<VecDict<String, Doc>>, cfg: &mut Config) -> Result<Doc> {
+    let layout = meta::take(&mut doc, inherited.is_some(), cfg)?;
+    let mut extends = doc.take_custom("extends");
+    let inherited = inherited.unwrap_or_default();
+    if extends.len() > 1 {
+        let (tag, _) = &extends[1];
+        return Err(Error::new(ErrorKind::DuplicateTag(String::from("!--?extends--"))).at(tag.span()))
+    }
+    let (link, span) = match (extends.pop(), layout) {
+        (Some((mut tag, _)), _) => (custom::required(&mut tag, "link")?, tag.span()),
+        (None, Some(layout)) => layout,
+        (None, None) => {
+            cfg.push_blocks(inherited);
+            let res = parse::expand(doc, cfg);
+            cfg.pop_blocks();
+            return res
+        }
+    };
+    let mut blocks = inherited.clone();
+    for (set, body) in doc.take_custom("set") {
+        let span = set.span();
+        var::set(set, body, cfg).map_err(|e| e.at(span))?;
+    }
+    for (mut block, body) in doc.take_custom("block") {
+        let name = custom::required(&mut block, "name")?;
+        if blocks.contains(&name) {
+            continue
+        }
+        // The defaults of a layout's blocks may hold blocks of its own layout, which the page fills.
+        cfg.push_blocks(inherited.clone());
+        let content = parse::expand(body.unwrap_or_else(Doc::empty), cfg);
+        cfg.pop_blocks();
+        blocks.push_unique(name, content?);
+    }
+    let path = cfg.resolve_relative(&link);
+    let source = cfg.read(&path).map_err(|e| e.at(span))?;
+    let (file, page_source) = cfg.current()
+        .map(|(file, source)| (file.to_path_buf(), source.clone()))
+        .expect("extends is expanded while a file is being expanded");
+    cfg.inherit_blocks(blocks);
+    parse::into_doc(source, &path, cfg)
+        .map_err(|e| e.included_from(&file, &page_source, span))
+}
+
+/// `<!--?block name="content"-->default<!--?/block-->` outputs the content the extending page
+/// gave for the block, or its own body if the page gave none.
+pub fn run_block(mut tag: Tag, body: Option<Doc>, cfg: &mut Config) -> Result<Doc> {
+    let name = custom::required(&mut tag, "name")?;
+    if let Some(content) = cfg.block(&name) {
+        return Ok(content.clone())
+    }
+    parse::expand(body.unwrap_or_else(Doc::empty), cfg)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::Path;
+    use super::*;
+
+    fn compile(dir: &Path, page: &str) -> Result<String> {
+        let page = dir.join(page);
+        let (mut cfg, source) = Config::new(page.to_string_lossy().into_owned(), Default::default());
+        parse::into_page(source?, &page, &mut cfg).map(|doc| doc.to_string())
+    }
+
+    #[test]
+    fn blocks_pass_through_a_chain_of_layouts() {
+        let dir = std::env::temp_dir().join(format!("extends-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let files = [
+            ("base1.html", r#"<!--?block name="A"-->A1<!--?/block-->"#),
+            ("base2.html", r#"<!--?extends link="base1.html"--><!--?block name="A"-->A2[<!--?block name="B"-->B2<!--?/block-->]<!--?/block-->"#),
+            ("page.html", r#"<!--?extends link="base2.html"--><!--?block name="B"-->B-page<!--?/block-->"#),
+            ("bare.html", r#"<!--?extends link="base2.html"-->"#),
+        ];
+        for (name, source) in files {
+            fs::write(dir.join(name), source).unwrap();
+        }
+        let page = compile(&dir, "page.html");
+        let bare = compile(&dir, "bare.html");
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(page.unwrap(), "A2[B-page]");
+        assert_eq!(bare.unwrap(), "A2[B2]");
+    }
+}
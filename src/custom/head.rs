@@ -0,0 +1,104 @@
+use crate::config::Config;
+use crate::error::Result;
+use crate::parse::component::Component;
+use crate::parse::doc::Doc;
+use crate::parse::select::Selector;
+use crate::parse::tag::Tag;
+use crate::parse::tree::{Node, NodeId, Tree};
+use crate::util;
+use crate::util::VecDict;
+
+/// Head children that belong to the page alone and are never hoisted.
+const PAGE_ONLY: [&str; 2] = ["title", "base"];
+
+/// Takes the children of the `<head>` of a document, if it has one.
+pub fn children(doc: &Doc) -> Option<Doc> {
+    let tree = Tree::new(doc.clone());
+    let head = tree.find(tree.root(), &Selector::tag("head"))?;
+    Some(tree.inner(head))
+}
+
+/// Keeps the children of the `<head>` of an included file, so that they end up in the head of the page.
+pub fn hoist(children: &Doc, cfg: &mut Config) {
+    let tree = Tree::new(children.clone());
+    for (key, item) in items(&tree, tree.root()) {
+        cfg.hoist(key, item);
+    }
+}
+
+/// Moves the `<head>` children kept from included files into the head of the page,
+/// leaving out those the page already has. If the page has no head, they go before its `<body>`,
+/// and without either, after its doctype.
+pub fn merge(tree: &mut Tree, cfg: &mut Config) -> Result<()> {
+    let hoisted = cfg.take_hoisted();
+    if hoisted.is_empty() {
+        return Ok(())
+    }
+    let root = tree.root();
+    let head = tree.find(root, &Selector::tag("head"));
+    let own = head.map_or_else(VecDict::new, |head| items(tree, head));
+    let mut merged = Doc::empty();
+    for (key, item) in hoisted {
+        if !own.contains(&key) {
+            merged.append(item);
+        }
+    }
+    match (head, tree.find(root, &Selector::tag("body"))) {
+        (Some(head), _) => tree.append(head, merged),
+        (None, Some(body)) => {
+            let parent = tree.parent(body).unwrap_or(root);
+            let index = tree.children(parent).iter().position(|&child| child == body).unwrap_or(0);
+            tree.insert(parent, index, merged)
+        }
+        (None, None) => tree.insert(root, prologue_len(tree), merged),
+    };
+    Ok(())
+}
+
+/// The number of leading doctypes, comments and whitespace of a page without a head or body,
+/// which hoisted items go after.
+fn prologue_len(tree: &Tree) -> usize {
+    tree.children(tree.root()).iter()
+        .take_while(|&&child| match tree.node(child) {
+            Node::Leaf(Component::Text(text)) => text.trim().is_empty(),
+            Node::Leaf(Component::DocType(_) | Component::Comment(_) | Component::CustomComment(_)) => true,
+            _ => false,
+        })
+        .count()
+}
+
+/// Splits the children of a node into items, keyed by what they refer to if they are
+/// stylesheets or scripts, by what they set if they are metadata, and by their markup otherwise.
+fn items(tree: &Tree, parent: NodeId) -> VecDict<String, Doc> {
+    let mut res = VecDict::new();
+    for &child in tree.children(parent) {
+        let key = match tree.node(child) {
+            Node::Leaf(Component::Text(text)) if text.trim().is_empty() => continue,
+            Node::Element { start, .. } if PAGE_ONLY.contains(&start.tag()) => continue,
+            Node::Element { start, .. } => reference(start),
+            _ => None,
+        };
+        let item = tree.outer(child);
+        res.push_unique(key.unwrap_or_else(|| item.to_string()), item);
+    }
+    res
+}
+
+/// The resource a stylesheet link or an external script refers to, or what a `<meta>` sets:
+/// the charset, of which a page has one, or the value of its `name` or `http-equiv`.
+fn reference(tag: &Tag) -> Option<String> {
+    let attribute = |key: &str| tag.attributes()
+        .find(|(k, _)| k.eq_ignore_ascii_case(key))
+        .map(|(_, v)| util::unquote(v).to_string());
+    match tag.tag() {
+        "link" => Some(format!("link {} {}", attribute("rel")?, attribute("href")?)),
+        "script" => Some(format!("script {}", attribute("src")?)),
+        "meta" if attribute("charset").is_some() => Some(String::from("meta charset")),
+        "meta" => match (attribute("name"), attribute("http-equiv")) {
+            (Some(name), _) => Some(format!("meta name {}", name.to_ascii_lowercase())),
+            (None, Some(header)) => Some(format!("meta http-equiv {}", header.to_ascii_lowercase())),
+            (None, None) => None,
+        },
+        _ => None,
+    }
+}
@@ -1,43 +1,122 @@
+use std::rc::Rc;
 use crate::config::Config;
+use crate::custom;
+use crate::error::{Error, ErrorKind, Result};
+use crate::custom::head;
 use crate::parse;
 use crate::parse::doc::Doc;
+use crate::parse::select::Selector;
 use crate::parse::tag::Tag;
+use crate::parse::tree::Tree;
+use crate::util;
+use crate::util::VecDict;
 
-pub fn run(mut tag: Tag, cfg: &mut Config) -> Option<Doc> {
-    let Some(link_raw) = tag.consume("link")
-        else {
-            eprintln!("[WARN] attribute 'link' is essential for ?include");
-            return None
-        };
-    let link = link_raw.trim_matches('"');
-    let source = cfg.read_relative(link)
-        .expect(format!("[ERROR] Failed to read the linked file: {}", link).as_str());
-    let mut linked_doc = parse::into_doc(source, cfg);
-    let begin = linked_doc.find_tags("body");
-    let end = linked_doc.find_tags("/body");
-    validate_body_tag(&begin, &end);
-    if begin.len() == 1 && begin.len() == 1 {
-        linked_doc.extract(begin[0]+1..end[0]);
+/// `<!--?include link="card.html"-->` expands to the body of the linked file.
+/// `select="#pricing"` takes the element matched by a selector instead, and `part="head"`
+/// the contents of the first element with that tag name; only the part taken is expanded.
+/// The children of the `<head>` of the linked file are moved to the head of the page, except with `part="head"`.
+/// The other attributes become parameters of the linked file. A paired include,
+/// `<!--?include link="panel.html"-->...<!--?/include-->`, passes its body to the slots of the linked file.
+pub fn run(mut tag: Tag, body: Option<Doc>, cfg: &mut Config) -> Result<Doc> {
+    let link = custom::required(&mut tag, "link")?;
+    let path = cfg.resolve_relative(&link);
+    let source = cfg.read(&path)?;
+    let selection = selection(&mut tag)?;
+    let mut props = VecDict::new();
+    for (key, value) in tag.attributes() {
+        props.push_unique(key.to_string(), util::unquote(value).to_string());
+    }
+    let slots = match body {
+        Some(body) => collect_slots(body, cfg)?,
+        None => VecDict::new(),
+    };
+    cfg.push_include(props, slots);
+    let linked_source = Rc::clone(&source);
+    let mut head_len = 0;
+    let linked_doc = match &selection {
+        Some((selector, inner)) => {
+            let part = format!("{}=\"{}\"", if *inner { "part" } else { "select" }, selector);
+            parse::into_doc_with(source, &path, Some(&part), cfg, |doc| {
+                let head = match inner {
+                    true if *selector == Selector::tag("head") => None,
+                    _ => head::children(&doc),
+                };
+                let selected = select(doc, selector, *inner)?;
+                let Some(mut head) = head else { return Ok(selected) };
+                head_len = head.len();
+                head.append(selected);
+                Ok(head)
+            })
+        }
+        None => parse::into_doc(source, &path, cfg),
+    };
+    cfg.pop_include();
+    let mut linked_doc = linked_doc?;
+    if selection.is_some() {
+        let selected = linked_doc.split_off(head_len);
+        head::hoist(&linked_doc, cfg);
+        return Ok(selected)
+    }
+    let mut tree = Tree::new(linked_doc);
+    let root = tree.root();
+    let head = tree.find(root, &Selector::tag("head"));
+    if let Some(head) = head {
+        head::hoist(&tree.inner(head), cfg);
+    }
+    match tree.find_all(root, &Selector::tag("body"))[..] {
+        [] => {
+            if let Some(head) = head {
+                tree.detach(head);
+            }
+            Ok(tree.into_doc())
+        }
+        [body] => Ok(tree.inner(body)),
+        [_, second, ..] => Err(Error::new(ErrorKind::DuplicateTag(String::from("body")))
+            .at(tree.span(second))
+            .in_source(&path, &linked_source)),
     }
-    Some(linked_doc)
 }
 
-fn validate_body_tag(begin: &Vec<usize>, end: &Vec<usize>) -> bool {
-    if begin.len() > 1 {
-        eprintln!("[ERROR] Duplicate <body> tags found");
-        return false
-    }
-    if end.len() > 1 {
-        eprintln!("[ERROR] Duplicate </body> tags found");
-        return false
+/// Reads `select` or `part` from an include, with whether only the contents of the element are taken.
+fn selection(tag: &mut Tag) -> Result<Option<(Selector, bool)>> {
+    let select = tag.consume("select").map(|v| util::unquote(&v).to_string());
+    let part = tag.consume("part").map(|v| util::unquote(&v).to_string());
+    match (select, part) {
+        (Some(select), None) => Selector::parse(&select)
+            .map(|selector| Some((selector, false)))
+            .ok_or_else(|| ErrorKind::InvalidSelector(select).into()),
+        (None, Some(part)) if Selector::parse(&part).is_some_and(|s| s == Selector::tag(&part)) => {
+            Ok(Some((Selector::tag(&part), true)))
+        }
+        (None, Some(part)) => Err(ErrorKind::InvalidSelector(part).into()),
+        (Some(select), Some(part)) => {
+            Err(ErrorKind::InvalidSelector(format!("{} (select and part=\"{}\" exclude each other)", select, part)).into())
+        }
+        (None, None) => Ok(None),
     }
-    if begin.is_empty() && !end.is_empty() {
-        eprintln!("[ERROR] Did not found <body> for </body>");
-        return false
+}
+
+/// Narrows a linked document to the element matched by `selector`, or to its contents if `inner`.
+fn select(doc: Doc, selector: &Selector, inner: bool) -> Result<Doc> {
+    let tree = Tree::new(doc);
+    let node = tree.find(tree.root(), selector)
+        .ok_or_else(|| ErrorKind::NoMatch(selector.to_string()))?;
+    Ok(if inner { tree.inner(node) } else { tree.outer(node) })
+}
+
+/// Expands the contents of `<!--?fill name="..."-->...<!--?/fill-->` into named slots
+/// and the rest of the body into the default slot.
+fn collect_slots(mut body: Doc, cfg: &mut Config) -> Result<VecDict<String, Doc>> {
+    let mut slots = VecDict::new();
+    let fills = body.take_custom("fill");
+    let has_fills = !fills.is_empty();
+    for (mut fill, content) in fills {
+        let name = custom::required(&mut fill, "name")?;
+        let content = parse::expand(content.unwrap_or_else(Doc::empty), cfg)?;
+        slots.push_unique(name, content);
     }
-    if !begin.is_empty() && end.is_empty() {
-        eprintln!("[ERROR] Did not found </body> for <body>");
-        return false
+    if !(has_fills && body.is_blank()) {
+        slots.push_unique(String::new(), parse::expand(body, cfg)?);
     }
-    true
+    Ok(slots)
 }
@@ -0,0 +1,114 @@
+use std::path::PathBuf;
+use serde_json::{Map, Value};
+use crate::config::Config;
+use crate::custom;
+use crate::data;
+use crate::error::{Error, ErrorKind, Result};
+use crate::parse;
+use crate::parse::doc::Doc;
+use crate::parse::tag::Tag;
+use crate::site::Site;
+use crate::util;
+
+/// `<!--?for item in="data/team.json"-->...<!--?/for-->` expands its body once for each item
+/// of an array in a JSON, TOML or CSV file, with `at="team"` picking an array inside the file.
+/// The item is available as the variable `item`, its fields as `item.name`,
+/// and the position as `loop.index` (from 0), `loop.first`, `loop.last` and `loop.length`.
+/// A glob such as `in="posts/*.html"` iterates over the front matter of the matched pages instead,
+/// with the link to each page as `path`, ordered by path or by the key given as `sort="date"`;
+/// `reverse="true"` reverses the order.
+pub fn run(mut tag: Tag, body: Option<Doc>, cfg: &mut Config) -> Result<Doc> {
+    let Some(body) = body
+        else { return Err(Error::new(ErrorKind::UnmatchedTag(String::from("!--?for--"))).at(tag.span())) };
+    let (name, link) = header(&mut tag)?;
+    let value = if link.contains(['*', '?']) {
+        index(&mut tag, &link, cfg)?
+    } else {
+        let path = cfg.resolve_relative(&link);
+        cfg.read_data(&path)?
+    };
+    let at = tag.consume("at").map(|at| util::unquote(&at).to_string());
+    let items = match at.as_deref() {
+        Some(at) => data::lookup(&value, at),
+        None => Some(&value),
+    };
+    let Some(Value::Array(items)) = items
+        else {
+            let what = match at {
+                Some(at) => format!("'{}' in {}", at, link),
+                None => link,
+            };
+            return Err(ErrorKind::NotIterable(what).into())
+        };
+    let mut res = Doc::empty();
+    for (idx, item) in items.iter().enumerate() {
+        let mut vars = Vec::new();
+        data::flatten(&name, item, &mut vars);
+        vars.push((String::from("loop.index"), idx.to_string()));
+        vars.push((String::from("loop.first"), (idx == 0).to_string()));
+        vars.push((String::from("loop.last"), (idx + 1 == items.len()).to_string()));
+        vars.push((String::from("loop.length"), items.len().to_string()));
+        cfg.push_scope();
+        for (key, value) in vars {
+            cfg.set_var(key, value);
+        }
+        let expanded = parse::expand(body.clone(), cfg);
+        cfg.pop_scope();
+        res.append(expanded?);
+    }
+    Ok(res)
+}
+
+/// Collects the front matter of the pages matched by a glob, relative to the file being expanded.
+fn index(tag: &mut Tag, glob: &str, cfg: &mut Config) -> Result<Value> {
+    let split = glob.find(['*', '?'])
+        .and_then(|wildcard| glob[..wildcard].rfind('/'))
+        .map_or(0, |slash| slash + 1);
+    let (base, pattern) = glob.split_at(split);
+    let root = cfg.resolve_relative(base);
+    let mut site = Site::new(if root.as_os_str().is_empty() { PathBuf::from(".") } else { root });
+    site.pages = pattern.to_string();
+    site.partial_prefix = String::new();
+    let pages = site.find_pages(None)
+        .map_err(|e| Error::io(e, &site.root))?;
+    let mut items = Vec::new();
+    for page in pages {
+        let source = cfg.read(&page)?;
+        let mut meta = match data::front_matter(&source) {
+            Some(text) => data::parse_meta(text)
+                .map_err(|e| Error::new(ErrorKind::InvalidData(e)).in_file(&page))?,
+            None => Map::new(),
+        };
+        let relative = page.strip_prefix(&site.root).unwrap_or(&page);
+        let relative: Vec<_> = relative.components()
+            .map(|part| part.as_os_str().to_string_lossy().into_owned())
+            .collect();
+        meta.insert(String::from("path"), Value::String(format!("{}{}", base, relative.join("/"))));
+        items.push(Value::Object(meta));
+    }
+    if let Some(key) = tag.consume("sort") {
+        let key = util::unquote(&key);
+        let sort_key = |item: &Value| match data::lookup(item, key) {
+            Some(Value::String(text)) => text.clone(),
+            Some(Value::Null) | None => String::new(),
+            Some(other) => other.to_string(),
+        };
+        items.sort_by_cached_key(sort_key);
+    }
+    if tag.consume("reverse").is_some_and(|reverse| util::unquote(&reverse) != "false") {
+        items.reverse();
+    }
+    Ok(Value::Array(items))
+}
+
+/// Reads `item in="path"`, the name of the loop variable and the link to the data file.
+/// The name is the attribute without a value, `item` if there is none.
+fn header(tag: &mut Tag) -> Result<(String, String)> {
+    let link = custom::required(tag, "in")?;
+    let name = tag.attributes()
+        .find(|(_, value)| value.is_empty())
+        .map_or("item", |(key, _)| key)
+        .to_string();
+    tag.consume(&name);
+    Ok((name, link))
+}
@@ -0,0 +1,39 @@
+use serde_json::Value;
+use crate::config::Config;
+use crate::data;
+use crate::error::{Error, ErrorKind, Result};
+use crate::parse::component::Component;
+use crate::parse::doc::Doc;
+use crate::parse::span::Span;
+
+/// Removes the front matter, `<!--?meta ... -->` in TOML or YAML at the start of a file, and sets its keys
+/// as variables of the file. A layout only sets those the page extending it did not set.
+/// Returns the `layout` key with the span of the block, to be extended like `<!--?extends-->`.
+pub fn take(doc: &mut Doc, is_layout: bool, cfg: &mut Config) -> Result<Option<(String, Span)>> {
+    let Some(idx) = position(doc) else { return Ok(None) };
+    let span = doc.span(idx);
+    if (0..idx).any(|i| !matches!(&doc[i], Component::Text(text) if text.trim().is_empty())) {
+        return Err(Error::new(ErrorKind::MisplacedMeta).at(span))
+    }
+    let Some(Component::CustomComment(text)) = doc.remove(idx) else { unreachable!() };
+    let meta = data::parse_meta(&text["meta".len()..])
+        .map_err(|e| Error::new(ErrorKind::InvalidData(e)).at(span))?;
+    let layout = meta.get("layout").and_then(Value::as_str).map(|link| (link.to_string(), span));
+    let mut vars = Vec::new();
+    for (key, value) in &meta {
+        data::flatten(key, value, &mut vars);
+    }
+    for (key, value) in vars {
+        if !is_layout || cfg.var(&key).is_none() {
+            cfg.set_var(key, value);
+        }
+    }
+    if let Some(extra) = position(doc) {
+        return Err(Error::new(ErrorKind::MisplacedMeta).at(doc.span(extra)))
+    }
+    Ok(layout)
+}
+
+fn position(doc: &Doc) -> Option<usize> {
+    (0..doc.len()).find(|&i| matches!(&doc[i], Component::CustomComment(text) if data::is_front_matter(text.as_bytes())))
+}
@@ -0,0 +1,38 @@
+use crate::config::Config;
+use crate::custom;
+use crate::error::{ErrorKind, Result};
+use crate::parse::doc::Doc;
+use crate::parse::tag::Tag;
+use crate::util;
+
+/// `<!--?prop name="title"-->`, or `<!--?prop title-->`, outputs a parameter passed by the include,
+/// or the value of the `default` attribute if it was not passed.
+/// As with `<!--?var-->`, it is HTML-escaped unless `raw="true"` is given or it is inside a script.
+pub fn run(mut tag: Tag, _body: Option<Doc>, cfg: &mut Config) -> Result<Doc> {
+    let raw = tag.consume("raw").is_some_and(|raw| util::unquote(&raw) != "false");
+    let name = match tag.consume("name") {
+        Some(name) => util::unquote(&name).to_string(),
+        None => {
+            let name = tag.attributes()
+                .find(|(_, value)| value.is_empty())
+                .map(|(key, _)| key.to_string())
+                .ok_or_else(|| custom::missing(&tag, "name"))?;
+            tag.consume(&name);
+            name
+        }
+    };
+    let value = match (cfg.prop(&name), tag.consume("default")) {
+        (Some(value), _) => value.to_string(),
+        (None, Some(value)) => util::unquote(&value).to_string(),
+        (None, None) => return Err(ErrorKind::MissingProp(name).into()),
+    };
+    Ok(Doc::from_text(if raw || cfg.in_script() { value } else { util::escape_html(&value) }))
+}
+
+/// `<!--?props title="Untitled"-->` declares default values of the parameters of a partial.
+pub fn run_defaults(tag: Tag, _body: Option<Doc>, cfg: &mut Config) -> Result<Doc> {
+    for (key, value) in tag.attributes() {
+        cfg.set_default_prop(key.to_string(), util::unquote(value).to_string());
+    }
+    Ok(Doc::empty())
+}
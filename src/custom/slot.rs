@@ -0,0 +1,21 @@
+use crate::config::Config;
+use crate::error::Result;
+use crate::parse;
+use crate::parse::doc::Doc;
+use crate::parse::tag::Tag;
+use crate::util;
+
+/// `<!--?slot-->` or `<!--?slot name="footer"-->` outputs the content the include passed for the slot.
+/// If none was passed, a paired slot outputs its own body as the fallback.
+pub fn run(mut tag: Tag, body: Option<Doc>, cfg: &mut Config) -> Result<Doc> {
+    let name = tag.consume("name")
+        .map(|name| util::unquote(&name).to_string())
+        .unwrap_or_default();
+    if let Some(content) = cfg.slot(&name) {
+        return Ok(content.clone())
+    }
+    match body {
+        Some(body) => parse::expand(body, cfg),
+        None => Ok(Doc::empty()),
+    }
+}
@@ -0,0 +1,101 @@
+use std::ops::Range;
+use crate::config::Config;
+use crate::custom;
+use crate::error::{Error, ErrorKind, Result};
+use crate::parse;
+use crate::parse::doc::Doc;
+use crate::parse::span::Span;
+use crate::parse::tag::Tag;
+use crate::util;
+
+/// `<!--?set name="title" value="About"-->` sets a variable of the file being expanded.
+/// A paired `<!--?set name="nav"-->...<!--?/set-->` sets it to its expanded body instead.
+pub fn run_set(tag: Tag, body: Option<Doc>, cfg: &mut Config) -> Result<Doc> {
+    set(tag, body, cfg)?;
+    Ok(Doc::empty())
+}
+
+/// Sets a variable as `<!--?set-->` does, outside of a tree, e.g. before a layout is expanded.
+pub fn set(mut tag: Tag, body: Option<Doc>, cfg: &mut Config) -> Result<()> {
+    let name = custom::required(&mut tag, "name")?;
+    let value = match (tag.consume("value"), body) {
+        (Some(value), _) => util::unquote(&value).to_string(),
+        (None, Some(body)) => parse::expand(body, cfg)?.to_string(),
+        (None, None) => return Err(custom::missing(&tag, "value")),
+    };
+    cfg.set_var(name, value);
+    Ok(())
+}
+
+/// `<!--?var name="title"-->` outputs a variable, HTML-escaped unless `raw="true"` is given
+/// or it is inside a `<script>` or `<style>` element, as `{{title}}` is.
+/// `default="..."` is output if the variable is not set.
+pub fn run(mut tag: Tag, _body: Option<Doc>, cfg: &mut Config) -> Result<Doc> {
+    let name = custom::required(&mut tag, "name")?;
+    let raw = tag.consume("raw").is_some_and(|raw| util::unquote(&raw) != "false");
+    let value = match cfg.var(&name) {
+        Some(value) => value.to_string(),
+        None => match tag.consume("default") {
+            Some(value) => util::unquote(&value).to_string(),
+            None => return Err(ErrorKind::UndefinedVariable(name).into()),
+        },
+    };
+    Ok(Doc::from_text(if raw || cfg.in_script() { value } else { util::escape_html(&value) }))
+}
+
+/// Replaces `{{name}}` in the attribute values of a tag with the parameter or the variable `name`, HTML-escaped.
+/// `\{{` stands for a literal `{{`.
+pub fn interpolate_tag(tag: &mut Tag, cfg: &Config) -> Result<()> {
+    let mut res = Ok(());
+    tag.for_each_value_mut(|attribute, value| {
+        if res.is_ok() && value.contains("{{") {
+            res = replace(value, true, cfg)
+                .map(|replaced| *value = replaced)
+                .map_err(|(key, _)| (key, attribute.to_string()));
+        }
+    });
+    res.map_err(|(key, attribute)| {
+        Error::new(ErrorKind::UndefinedVariable(key)).at(tag.attribute_span(&attribute).unwrap_or(tag.span()))
+    })
+}
+
+/// Replaces `{{name}}` in a text parsed from `span` as `interpolate_tag` does,
+/// but without escaping in the text of `<script>` and `<style>` elements.
+pub fn interpolate_text(text: &mut String, span: Span, cfg: &Config) -> Result<()> {
+    if !text.contains("{{") {
+        return Ok(())
+    }
+    let unchanged = span.end - span.start == text.len();
+    *text = replace(text, !cfg.in_script(), cfg).map_err(|(key, range)| {
+        let at = match unchanged {
+            true => Span::new(span.start + range.start, span.start + range.end),
+            false => span,
+        };
+        Error::new(ErrorKind::UndefinedVariable(key)).at(at)
+    })?;
+    Ok(())
+}
+
+/// Replaces the names in `value`, or returns the first name that is neither a parameter nor a variable,
+/// with the range of its `{{...}}` in `value`.
+fn replace(value: &str, escape: bool, cfg: &Config) -> std::result::Result<String, (String, Range<usize>)> {
+    let mut res = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(begin) = rest.find("{{") {
+        if rest[..begin].ends_with('\\') {
+            res += &rest[..begin - 1];
+            res += "{{";
+            rest = &rest[begin + 2..];
+            continue
+        }
+        let Some(len) = rest[begin..].find("}}") else { break };
+        let key = rest[begin + 2..begin + len].trim();
+        res += &rest[..begin];
+        let offset = value.len() - rest.len() + begin;
+        let value = cfg.prop(key).or_else(|| cfg.var(key))
+            .ok_or_else(|| (key.to_string(), offset..offset + len + 2))?;
+        res += &if escape { util::escape_html(value) } else { value.to_string() };
+        rest = &rest[begin + len + 2..];
+    }
+    Ok(res + rest)
+}
@@ -0,0 +1,135 @@
+use std::path::Path;
+use serde_json::{Map, Number, Value};
+use yaml_rust2::{Yaml, YamlLoader};
+
+/// Parses a data file by its extension: `.json`, `.toml` or `.csv`.
+/// A CSV file becomes an array of objects keyed by its header row.
+pub fn parse(path: &Path, source: &str) -> Result<Value, String> {
+    let ext = path.extension().unwrap_or_default().to_string_lossy().to_ascii_lowercase();
+    match ext.as_str() {
+        "json" => serde_json::from_str(source).map_err(|e| e.to_string()),
+        "toml" => {
+            let table: toml::Table = toml::from_str(source).map_err(|e| e.to_string())?;
+            Ok(from_toml(toml::Value::Table(table)))
+        }
+        "csv" => parse_csv(source),
+        _ => Err(format!("Unknown data format '{}', expected json, toml or csv", ext)),
+    }
+}
+
+/// Finds the front matter of a page: the text of a `<!--?meta ... -->` block at its start.
+pub fn front_matter(source: &str) -> Option<&str> {
+    let rest = source.trim_start().strip_prefix("<!--?meta")?;
+    let (text, _) = rest.split_once("-->")?;
+    (text.is_empty() || text.starts_with(char::is_whitespace)).then_some(text)
+}
+
+/// Whether the text of a custom comment is a front matter block, i.e. starts with `meta` and whitespace.
+pub fn is_front_matter(comment: &[u8]) -> bool {
+    comment.strip_prefix(b"meta")
+        .is_some_and(|text| text.first().is_none_or(u8::is_ascii_whitespace))
+}
+
+/// Parses front matter written in TOML or, failing that, in YAML. Either must be a table of keys.
+pub fn parse_meta(text: &str) -> Result<Map<String, Value>, String> {
+    let toml_error = match toml::from_str::<toml::Table>(text) {
+        Ok(table) => {
+            let Value::Object(map) = from_toml(toml::Value::Table(table)) else { unreachable!() };
+            return Ok(map)
+        }
+        Err(e) => e.to_string(),
+    };
+    match YamlLoader::load_from_str(text).map(|docs| docs.into_iter().next()) {
+        Ok(None) => Ok(Map::new()),
+        Ok(Some(Yaml::Hash(hash))) => {
+            let Value::Object(map) = from_yaml(Yaml::Hash(hash)) else { unreachable!() };
+            Ok(map)
+        }
+        _ => Err(toml_error),
+    }
+}
+
+fn from_toml(value: toml::Value) -> Value {
+    match value {
+        toml::Value::String(text) => Value::String(text),
+        toml::Value::Integer(n) => Value::from(n),
+        toml::Value::Float(n) => Number::from_f64(n).map_or(Value::Null, Value::Number),
+        toml::Value::Boolean(b) => Value::Bool(b),
+        toml::Value::Datetime(date) => Value::String(date.to_string()),
+        toml::Value::Array(items) => Value::Array(items.into_iter().map(from_toml).collect()),
+        toml::Value::Table(table) => Value::Object(table.into_iter()
+            .map(|(key, value)| (key, from_toml(value)))
+            .collect()),
+    }
+}
+
+fn from_yaml(value: Yaml) -> Value {
+    match value {
+        Yaml::String(text) => Value::String(text),
+        Yaml::Integer(n) => Value::from(n),
+        Yaml::Real(text) => text.parse::<f64>().ok()
+            .and_then(Number::from_f64)
+            .map_or(Value::String(text), Value::Number),
+        Yaml::Boolean(b) => Value::Bool(b),
+        Yaml::Array(items) => Value::Array(items.into_iter().map(from_yaml).collect()),
+        Yaml::Hash(hash) => Value::Object(hash.into_iter()
+            .map(|(key, value)| {
+                let key = match key {
+                    Yaml::String(key) => key,
+                    Yaml::Integer(n) => n.to_string(),
+                    Yaml::Real(key) => key,
+                    Yaml::Boolean(b) => b.to_string(),
+                    _ => String::new(),
+                };
+                (key, from_yaml(value))
+            })
+            .collect()),
+        _ => Value::Null,
+    }
+}
+
+fn parse_csv(source: &str) -> Result<Value, String> {
+    let mut reader = csv::Reader::from_reader(source.as_bytes());
+    let headers = reader.headers().map_err(|e| e.to_string())?.clone();
+    let mut rows = Vec::new();
+    for record in reader.records() {
+        let record = record.map_err(|e| e.to_string())?;
+        let row: Map<String, Value> = headers.iter()
+            .zip(record.iter())
+            .map(|(key, value)| (key.to_string(), Value::String(value.to_string())))
+            .collect();
+        rows.push(Value::Object(row));
+    }
+    Ok(Value::Array(rows))
+}
+
+/// Looks up a `.`-separated path of keys and array indices, e.g. `team.0.name`.
+pub fn lookup<'a>(value: &'a Value, path: &str) -> Option<&'a Value> {
+    path.split('.')
+        .filter(|key| !key.is_empty())
+        .try_fold(value, |value, key| match value {
+            Value::Object(map) => map.get(key),
+            Value::Array(items) => items.get(key.parse::<usize>().ok()?),
+            _ => None,
+        })
+}
+
+/// Flattens a value into variables: scalars under `name`, and the fields of objects
+/// and the items of arrays under `name.key` and `name.0`, recursively.
+pub fn flatten(name: &str, value: &Value, dest: &mut Vec<(String, String)>) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map {
+                flatten(&format!("{}.{}", name, key), value, dest);
+            }
+        }
+        Value::Array(items) => {
+            for (idx, value) in items.iter().enumerate() {
+                flatten(&format!("{}.{}", name, idx), value, dest);
+            }
+        }
+        Value::String(text) => dest.push((name.to_string(), text.clone())),
+        Value::Null => dest.push((name.to_string(), String::new())),
+        other => dest.push((name.to_string(), other.to_string())),
+    }
+}
@@ -0,0 +1,230 @@
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
+use std::{error, fmt, io, result};
+use crate::parse::span::Span;
+
+pub type Result<T> = result::Result<T, Error>;
+
+#[derive(Debug)]
+pub enum ErrorKind {
+    /// A file could not be read or written.
+    Io(io::Error),
+    /// A comment, tag or doctype is not closed, e.g. `<!--` without `-->`.
+    Unclosed(&'static str),
+    /// An attribute has `=` but no value; it is kept with an empty value. Only warned about.
+    NoAttributeValue(String),
+    /// A tag has an attribute more than once; the first is kept. Only warned about.
+    DuplicateAttribute(String),
+    /// A file is not valid UTF-8; the invalid bytes are replaced. Only warned about.
+    InvalidUtf8,
+    /// A page to compile is not a path to a file, e.g. an empty one.
+    InvalidPage(PathBuf),
+    /// A custom tag misses an attribute it requires.
+    MissingAttribute { tag: String, key: String },
+    /// A custom tag has an attribute it does not take.
+    UnknownAttribute { tag: String, key: String },
+    /// A file includes itself, directly or through other files. Holds the loop, ending with the repeated file,
+    /// each with the part of it taken if not all of it.
+    CircularInclude(Vec<String>),
+    /// Includes are nested deeper than the configured maximum.
+    IncludeTooDeep(usize),
+    /// A partial refers to a parameter that was neither passed nor given a default.
+    MissingProp(String),
+    /// A variable is neither set nor defined, and no default is given.
+    UndefinedVariable(String),
+    /// A data file could not be parsed.
+    InvalidData(String),
+    /// A front matter block is not at the start of its file.
+    MisplacedMeta,
+    /// A loop is given data that is not an array.
+    NotIterable(String),
+    /// No handler is registered for a custom tag.
+    UnknownCustomTag(String),
+    /// A tag that may appear once appears more than once, e.g. `<body>`.
+    DuplicateTag(String),
+    /// A start tag has no end tag or the other way around.
+    UnmatchedTag(String),
+    /// The `select` attribute of an include is not a selector.
+    InvalidSelector(String),
+    /// Nothing in an included file matches the `select` or `part` of the include.
+    NoMatch(String),
+}
+
+impl Display for ErrorKind {
+    fn fmt(&self, fmt: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(fmt, "{}", e),
+            Self::Unclosed(what) => write!(fmt, "Unclosed {}", what),
+            Self::NoAttributeValue(key) => {
+                write!(fmt, "There is no value of an attribute for key: {}, an empty value is used", key)
+            }
+            Self::DuplicateAttribute(key) => write!(fmt, "Duplicate attribute '{}', the first is kept", key),
+            Self::InvalidUtf8 => write!(fmt, "Not valid UTF-8, invalid bytes are replaced"),
+            Self::InvalidPage(page) => write!(fmt, "Not a path to a file: '{}'", page.display()),
+            Self::MissingAttribute { tag, key } => {
+                write!(fmt, "Attribute '{}' is essential for ?{}", key, tag)
+            }
+            Self::UnknownAttribute { tag, key } => write!(fmt, "Attribute '{}' is not known to ?{}", key, tag),
+            Self::CircularInclude(files) => write!(fmt, "Circular include: {}", files.join(" -> ")),
+            Self::IncludeTooDeep(max) => {
+                write!(fmt, "Includes are nested deeper than the maximum depth of {}", max)
+            }
+            Self::MissingProp(key) => {
+                write!(fmt, "Parameter '{}' was not passed and has no default", key)
+            }
+            Self::UndefinedVariable(key) => write!(fmt, "Variable '{}' is not set", key),
+            Self::InvalidData(e) => write!(fmt, "Invalid data: {}", e),
+            Self::MisplacedMeta => write!(fmt, "<!--?meta--> must come first in its file"),
+            Self::NotIterable(what) => write!(fmt, "Cannot iterate over {}, which is not an array", what),
+            Self::UnknownCustomTag(tag) => write!(fmt, "Unknown custom tag: ?{}", tag),
+            Self::DuplicateTag(tag) => write!(fmt, "Duplicate <{}> tags found", tag),
+            Self::UnmatchedTag(tag) => write!(fmt, "Did not find the matching tag for <{}>", tag),
+            Self::InvalidSelector(selector) => write!(fmt, "Invalid selector: {}", selector),
+            Self::NoMatch(selector) => write!(fmt, "No element matches '{}'", selector),
+        }
+    }
+}
+
+/// Where in its file an error was raised.
+#[derive(Debug, Clone)]
+pub struct Location {
+    pub line: usize,
+    pub column: usize,
+    /// The offending line with the span underlined.
+    pub excerpt: String,
+}
+
+/// A custom tag through which the compiler reached the file an error was raised in.
+#[derive(Debug, Clone)]
+pub struct IncludedFrom {
+    pub file: PathBuf,
+    pub line: usize,
+    pub column: usize,
+}
+
+/// An error raised while compiling, with the file and the span it was raised at if known.
+/// Once the file is set, the span and the location are final.
+#[derive(Debug)]
+pub struct Error {
+    inner: Box<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    kind: ErrorKind,
+    file: Option<PathBuf>,
+    span: Option<Span>,
+    location: Option<Location>,
+    /// The include chain, innermost first.
+    included_from: Vec<IncludedFrom>,
+}
+
+impl Error {
+    pub fn new(kind: ErrorKind) -> Self {
+        Self {
+            inner: Box::new(Inner {
+                kind,
+                file: None,
+                span: None,
+                location: None,
+                included_from: Vec::new(),
+            }),
+        }
+    }
+    pub fn io(e: io::Error, file: &Path) -> Self {
+        Self::new(ErrorKind::Io(e)).in_file(file)
+    }
+    /// Sets the span unless it is already known.
+    pub fn at(mut self, span: Span) -> Self {
+        if self.inner.file.is_none() {
+            self.inner.span.get_or_insert(span);
+        }
+        self
+    }
+    /// Sets the file unless it is already known.
+    pub fn in_file(mut self, file: &Path) -> Self {
+        if self.inner.file.is_none() {
+            self.inner.file = Some(file.to_path_buf());
+        }
+        self
+    }
+    /// Sets the file and resolves the span to a line and column in `source`, read from `file`.
+    pub fn in_source(mut self, file: &Path, source: &str) -> Self {
+        if self.inner.file.is_some() {
+            return self
+        }
+        if let Some(span) = self.inner.span {
+            let (line, column) = span.line_col(source);
+            self.inner.location = Some(Location {
+                line,
+                column,
+                excerpt: span.excerpt(source),
+            });
+        }
+        self.in_file(file)
+    }
+    /// Records that the error was raised below the custom tag at `span` in `file`.
+    /// Errors raised in `file` itself are left as they are.
+    pub fn included_from(mut self, file: &Path, source: &str, span: Span) -> Self {
+        if self.inner.file.is_none() {
+            return self
+        }
+        let (line, column) = span.line_col(source);
+        self.inner.included_from.push(IncludedFrom {
+            file: file.to_path_buf(),
+            line,
+            column,
+        });
+        self
+    }
+    pub fn kind(&self) -> &ErrorKind {
+        &self.inner.kind
+    }
+    pub fn file(&self) -> Option<&Path> {
+        self.inner.file.as_deref()
+    }
+    pub fn span(&self) -> Option<Span> {
+        self.inner.span
+    }
+    pub fn location(&self) -> Option<&Location> {
+        self.inner.location.as_ref()
+    }
+    pub fn include_chain(&self) -> &[IncludedFrom] {
+        &self.inner.included_from
+    }
+}
+
+impl From<ErrorKind> for Error {
+    fn from(kind: ErrorKind) -> Self {
+        Self::new(kind)
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, fmt: &mut Formatter<'_>) -> fmt::Result {
+        let inner = &self.inner;
+        match (&inner.file, &inner.location, inner.span) {
+            (Some(file), Some(loc), _) => write!(fmt, "{}:{}:{}: ", file.display(), loc.line, loc.column)?,
+            (Some(file), None, _) => write!(fmt, "{}: ", file.display())?,
+            (None, _, Some(span)) => write!(fmt, "byte {}: ", span.start)?,
+            (None, _, None) => {}
+        }
+        write!(fmt, "{}", inner.kind)?;
+        if let Some(loc) = &inner.location {
+            write!(fmt, "\n{}", loc.excerpt)?;
+        }
+        for frame in &inner.included_from {
+            write!(fmt, "\n  included from {}:{}:{}", frame.file.display(), frame.line, frame.column)?;
+        }
+        Ok(())
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match &self.inner.kind {
+            ErrorKind::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,11 @@
+pub mod config;
+pub mod custom;
+pub mod data;
+pub mod error;
+pub mod parse;
+pub mod util;
+pub mod serve;
+pub mod site;
+pub mod source;
+#[cfg(target_os = "linux")]
+pub mod watch;
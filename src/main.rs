@@ -1,22 +1,278 @@
-mod config;
-mod custom;
-mod parse;
-mod util;
-mod source;
+mod cli;
 
+use std::path::{Path, PathBuf};
 use std::time::Instant;
-use crate::config::Config;
-use crate::util::read_from_stdin;
+use std::{env, io, process};
+use linked_html_compiler::config::{Config, Output};
+use linked_html_compiler::error;
+use linked_html_compiler::parse;
+use linked_html_compiler::serve;
+use linked_html_compiler::serve::LiveState;
+use linked_html_compiler::util;
+use linked_html_compiler::util::read_from_stdin;
+#[cfg(target_os = "linux")]
+use {std::time::Duration, linked_html_compiler::watch::Watcher};
+use crate::cli::{Args, Command, Mode, Verbosity};
 
 fn main() {
-    println!("Enter file path to compile:");
-    let mut name = read_from_stdin().expect("Failed to read the input");
-    println!("[INFO] Compilation started.");
+    let command = cli::parse(env::args().skip(1)).unwrap_or_else(|msg| {
+        eprintln!("[ERROR] {}", msg);
+        eprintln!("Try '--help' for more information.");
+        process::exit(2);
+    });
+    let args = match command {
+        Command::Compile(args) => *args,
+        Command::Help => {
+            println!("{}", cli::USAGE);
+            return
+        }
+        Command::Version => {
+            println!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+            return
+        }
+    };
+    let pages = find_pages(&args).unwrap_or_else(|msg| {
+        eprintln!("[ERROR] {}", msg);
+        process::exit(1);
+    });
+    if let Err(msg) = check_outputs(&pages, &args.output) {
+        eprintln!("[ERROR] {}", msg);
+        process::exit(2);
+    }
+    if args.verbosity >= Verbosity::Normal {
+        println!("[INFO] Compilation started.");
+    }
     let timer = Instant::now();
+    let mut cfg = Config::with_output(args.output.clone());
+    cfg.set_root(args.root.clone());
+    for (key, value) in &args.defines {
+        cfg.define(key.clone(), value.clone());
+    }
+    if let Some(depth) = args.max_include_depth {
+        cfg.set_max_include_depth(depth);
+    }
+    let live = args.serve.as_ref().map(|_| LiveState::default());
+    let mut failed = 0;
+    let mut deps = Vec::new();
+    for page in &pages {
+        if !compile_and_report(page, &mut cfg, &args, live.as_ref()) {
+            failed += 1;
+        }
+        deps.push((page.clone(), cfg.dependencies().to_vec()));
+    }
+    if let (Some(addr), Some(live), Output::Dir { dir, .. }) = (&args.serve, &live, &args.output) {
+        let addr = serve::start(addr.as_str(), dir.clone(), live.clone()).unwrap_or_else(|e| {
+            eprintln!("[ERROR] Failed to serve on {}: {}", addr, e);
+            process::exit(1);
+        });
+        if args.verbosity >= Verbosity::Normal {
+            println!("[INFO] Serving {} at http://{}/", dir.display(), addr);
+        }
+    }
+    if args.watch {
+        if let Err(e) = watch(&args, &mut cfg, deps, live.as_ref()) {
+            eprintln!("[ERROR] Failed to watch the source files: {}", e);
+            process::exit(1);
+        }
+        return
+    }
+    if failed > 0 {
+        eprintln!("[ERROR] Failed to compile {} of {} page(s).", failed, pages.len());
+        process::exit(1);
+    }
+    if args.verbosity >= Verbosity::Normal {
+        println!("[INFO] Compilation finished. {} page(s), Time = {:?}", pages.len(), timer.elapsed());
+    }
+}
+
+fn find_pages(args: &Args) -> Result<Vec<PathBuf>, String> {
+    match &args.mode {
+        Mode::Compile(inputs) if inputs.is_empty() => Ok(vec![prompt_input()]),
+        Mode::Compile(inputs) => Ok(inputs.iter().map(PathBuf::from).collect()),
+        Mode::Build(site) => {
+            let exclude = match &args.output {
+                Output::Dir { dir, .. } => Some(dir.as_path()),
+                _ => None,
+            };
+            let pages = site.find_pages(exclude)
+                .map_err(|e| format!("Failed to read the source directory: {}: {}", site.root.display(), e))?;
+            if pages.is_empty() {
+                return Err(format!("No pages found in {} matching {}", site.root.display(), site.pages))
+            }
+            Ok(pages)
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn watch(args: &Args, cfg: &mut Config, mut pages: Vec<(PathBuf, Vec<PathBuf>)>, live: Option<&LiveState>) -> io::Result<()> {
+    let mut watcher = Watcher::new()?;
+    if args.verbosity >= Verbosity::Normal {
+        println!("[INFO] Watching for changes. Press Ctrl-C to stop.");
+    }
+    loop {
+        if let Mode::Build(site) = &args.mode {
+            ignore_missing(watcher.watch_dir(&site.root))?;
+        }
+        for (_, deps) in &pages {
+            for dep in deps {
+                ignore_missing(watcher.watch_file(dep))?;
+            }
+        }
+        let changed = watcher.wait(Duration::from_millis(100))?;
+        for path in &changed {
+            cfg.invalidate(path);
+        }
+        if matches!(args.mode, Mode::Build(_)) {
+            pages.retain(|(page, _)| page.exists());
+            for page in find_pages(args).unwrap_or_default() {
+                if !pages.iter().any(|(known, _)| *known == page) {
+                    let dep = util::normalize_path(&page);
+                    pages.push((page, vec![dep]));
+                }
+            }
+        }
+        let timer = Instant::now();
+        let mut count = 0;
+        for (page, deps) in pages.iter_mut() {
+            if !deps.iter().any(|dep| changed.contains(dep)) {
+                continue
+            }
+            compile_and_report(page, cfg, args, live);
+            *deps = cfg.dependencies().to_vec();
+            count += 1;
+        }
+        if count == 0 {
+            continue
+        }
+        if let Some(live) = live {
+            live.reload();
+        }
+        if args.verbosity >= Verbosity::Normal {
+            println!("[INFO] Recompiled {} page(s). Time = {:?}", count, timer.elapsed());
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn watch(_args: &Args, _cfg: &mut Config, _pages: Vec<(PathBuf, Vec<PathBuf>)>, _live: Option<&LiveState>) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "--watch is only supported on Linux"))
+}
+
+#[cfg(target_os = "linux")]
+fn ignore_missing(res: io::Result<()>) -> io::Result<()> {
+    match res {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        res => res,
+    }
+}
+
+fn prompt_input() -> PathBuf {
+    println!("Enter file path to compile:");
+    let mut name = read_from_stdin().unwrap_or_else(|_| {
+        eprintln!("[ERROR] Failed to read the input");
+        process::exit(1);
+    });
     name.retain(|c| c != '\r' && c != '\n' && c != '"');
-    let (mut cfg, Ok(source)) = Config::new(name.clone())
-        else { panic!("Failed to open the file to compile.") };
-    let doc = parse::into_doc(source, &mut cfg);
-    cfg.write_all(format!("{}", doc));
-    println!("[INFO] Compilation finished. Time = {:?}", timer.elapsed());
+    PathBuf::from(name)
+}
+
+fn check_outputs(pages: &[PathBuf], output: &Output) -> Result<(), String> {
+    let mut outputs: Vec<(PathBuf, &Path)> = Vec::new();
+    for page in pages {
+        let out_path = comparable(&output.resolve(page));
+        if out_path == comparable(page) {
+            return Err(format!("The output would overwrite the input: {}", page.display()))
+        }
+        if let Some((_, other)) = outputs.iter().find(|(path, _)| *path == out_path) {
+            return Err(format!("{} and {} would both be written to {}",
+                               other.display(), page.display(), out_path.display()))
+        }
+        outputs.push((out_path, page));
+    }
+    Ok(())
+}
+
+/// Spells a path the same way however it was given, e.g. `p.html`, `./p.html` or `s/../p.html`.
+/// The parent directory is canonicalized when it exists, so that links resolve too.
+fn comparable(path: &Path) -> PathBuf {
+    let path = util::normalize_path(path);
+    let parent = path.parent().filter(|p| !p.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    match (parent.canonicalize(), path.file_name()) {
+        (Ok(parent), Some(name)) => parent.join(name),
+        _ => env::current_dir().map(|dir| util::normalize_path(&dir.join(&path))).unwrap_or(path),
+    }
+}
+
+fn compile_and_report(page: &Path, cfg: &mut Config, args: &Args, live: Option<&LiveState>) -> bool {
+    let res = compile(page, cfg, args.verbosity).map_err(|e| e.to_string());
+    for warning in cfg.take_warnings() {
+        if args.verbosity >= Verbosity::Normal {
+            eprintln!("[WARN] {}", warning);
+        }
+    }
+    if let Err(msg) = &res {
+        eprintln!("[ERROR] {}", msg);
+    }
+    if let Some(live) = live {
+        live.record(cfg.out_path(), res.as_ref().map(|_| ()).map_err(String::as_str));
+    }
+    res.is_ok()
+}
+
+fn compile(page: &Path, cfg: &mut Config, verbosity: Verbosity) -> error::Result<()> {
+    let timer = Instant::now();
+    let source = cfg.open_page(page.to_path_buf())?;
+    let doc = parse::into_page(source, page, cfg)?;
+    cfg.write_all(format!("{}", doc))?;
+    cfg.flush()?;
+    if verbosity >= Verbosity::Verbose {
+        println!("[INFO] Compiled {} -> {}. Time = {:?}",
+                 page.display(), cfg.out_path().display(), timer.elapsed());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dir(root: &str, dir: &str) -> Output {
+        Output::Dir { root: PathBuf::from(root), dir: PathBuf::from(dir), name: String::from("{name}") }
+    }
+
+    #[test]
+    fn outputs_mirror_the_root() {
+        let output = dir(".", "dist");
+        assert_eq!(output.resolve(Path::new("p.html")), Path::new("dist/p.html"));
+        assert_eq!(output.resolve(Path::new("./a/p.html")), Path::new("dist/a/p.html"));
+        let output = dir("./s", "out");
+        assert_eq!(output.resolve(Path::new("s/a/p.html")), Path::new("out/a/p.html"));
+        assert_eq!(output.resolve(Path::new("./s/p.html")), Path::new("out/p.html"));
+        assert_eq!(Output::Pattern(String::from("{stem}.out")).resolve(Path::new("./a/p.html")),
+                   Path::new("./a/p.out"));
+    }
+
+    #[test]
+    fn outputs_must_not_overwrite_inputs() {
+        let pages = [PathBuf::from("p.html")];
+        assert!(check_outputs(&pages, &dir(".", ".")).is_err());
+        assert!(check_outputs(&pages, &dir("./", "./")).is_err());
+        assert!(check_outputs(&pages, &dir(".", "dist")).is_ok());
+        let pages = [PathBuf::from("s/a.html"), PathBuf::from("s/b.html")];
+        assert!(check_outputs(&pages, &dir("s", "./s")).is_err());
+        assert!(check_outputs(&pages, &dir("./s", "s/../s")).is_err());
+        assert!(check_outputs(&pages, &dir("./s", "dist")).is_ok());
+        assert!(check_outputs(&pages, &Output::Pattern(String::from("{name}"))).is_err());
+    }
+
+    #[test]
+    fn outputs_must_differ() {
+        let pages = [PathBuf::from("a/p.html"), PathBuf::from("./b/p.html")];
+        assert!(check_outputs(&pages, &Output::File(PathBuf::from("out.html"))).is_err());
+        assert!(check_outputs(&pages, &dir(".", "dist")).is_ok());
+        assert!(check_outputs(&pages, &dir("./a", "dist")).is_err());
+        let pages = [PathBuf::from("p.html"), PathBuf::from("./p.html")];
+        assert!(check_outputs(&pages, &dir(".", "dist")).is_err());
+    }
 }
@@ -1,24 +1,111 @@
-use std::process;
+use std::path::Path;
 use std::rc::Rc;
 use crate::config::Config;
 use crate::custom;
+use crate::error::Result;
 use crate::parse::doc::Doc;
 use crate::parse::component::Component;
+use crate::parse::tree::{Node, Tree};
 
 pub mod tag;
 pub mod component;
 pub mod doc;
+pub mod select;
+pub mod span;
+pub mod tree;
 
-pub fn into_doc(source: Rc<String>, cfg: &mut Config) -> Doc {
-    let mut doc = Doc::new(source).unwrap_or_else(|| {
-        process::exit(0);
-    });
-    doc.reassign_custom(|tag| {
-        if let Some(v) = custom::run(tag, cfg) {
-            Component::Pointer(v)
-        } else {
-            Component::Comment(String::from("?error"))
+/// Compiles a page: expands it, then runs the transforms over its tree,
+/// e.g. merging the `<head>` children of the files it includes into its head.
+pub fn into_page(source: Rc<String>, file: &Path, cfg: &mut Config) -> Result<Doc> {
+    let doc = into_doc(source, file, cfg)?;
+    let mut tree = Tree::new(doc);
+    custom::transform(&mut tree, cfg)
+        .map_err(|e| e.in_file(file))?;
+    Ok(tree.into_doc())
+}
+
+/// Parses `source`, read from `file`, and expands its custom tags.
+pub fn into_doc(source: Rc<String>, file: &Path, cfg: &mut Config) -> Result<Doc> {
+    into_doc_with(source, file, None, cfg, Ok)
+}
+
+/// Parses `source`, read from `file`, narrows the document to `part` with `prepare`
+/// and expands the custom tags left. `part` describes the part taken for cycle detection,
+/// e.g. `select="#card"`.
+pub fn into_doc_with<F>(source: Rc<String>, file: &Path, part: Option<&str>, cfg: &mut Config, prepare: F) -> Result<Doc>
+    where F: FnOnce(Doc) -> Result<Doc> {
+    let inherited = cfg.take_inherited_blocks();
+    cfg.enter(file, part, Rc::clone(&source))?;
+    let mut warnings = Vec::new();
+    let res = Doc::new(Rc::clone(&source), &mut warnings)
+        .and_then(prepare)
+        .and_then(|doc| custom::extends::expand(doc, inherited, cfg))
+        .map_err(|e| e.in_source(file, &source));
+    for warning in warnings {
+        cfg.warn(warning.in_source(file, &source));
+    }
+    cfg.leave();
+    res
+}
+
+/// Expands the custom tags of a document parsed from the file being expanded,
+/// e.g. the body of a paired custom tag. Errors are left for `into_doc_with` to place in that file.
+pub fn expand(doc: Doc, cfg: &mut Config) -> Result<Doc> {
+    let (file, source) = cfg.current()
+        .map(|(file, source)| (file.to_path_buf(), Rc::clone(source)))
+        .expect("expand is called while a file is being expanded");
+    let mut tree = Tree::new(doc);
+    let outer = cfg.in_script();
+    let res = expand_tree(&mut tree, &file, &source, cfg);
+    cfg.set_in_script(outer);
+    res?;
+    Ok(tree.into_doc())
+}
+
+/// Replaces the custom tags of a tree with their expansions and interpolates the variables in the other nodes,
+/// in document order, so that text sees the variables set before it.
+fn expand_tree(tree: &mut Tree, file: &Path, source: &str, cfg: &mut Config) -> Result<()> {
+    let outer = cfg.in_script();
+    for id in tree.descendants(tree.root()) {
+        let span = tree.span(id);
+        let in_script = tree.ancestors(id).into_iter()
+            .any(|ancestor| tree.element(ancestor).is_some_and(|tag| matches!(tag.tag(), "script" | "style")));
+        cfg.set_in_script(outer || in_script);
+        match tree.node_mut(id) {
+            Node::Element { start, .. } => custom::var::interpolate_tag(start, cfg)?,
+            Node::Leaf(Component::Text(text)) => custom::var::interpolate_text(text, span, cfg)?,
+            Node::Leaf(component @ Component::CustomTag(..)) => {
+                let Component::CustomTag(tag, _, body) = component.swap_null() else { unreachable!() };
+                let v = custom::run(tag, body, cfg)
+                    .map_err(|e| e.included_from(file, source, span).at(span))?;
+                *tree.node_mut(id) = Node::Leaf(Component::Pointer(v));
+            }
+            _ => {}
         }
-    });
-    doc
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compile(source: &str) -> String {
+        let mut cfg = Config::with_output(Default::default());
+        into_page(Rc::new(String::from(source)), Path::new("page.html"), &mut cfg).unwrap().to_string()
+    }
+
+    #[test]
+    fn variables_are_interpolated_in_document_order() {
+        let source = r#"<!--?set name="y" value="1"-->{{y}} <!--?var name="y"--> <!--?set name="y" value="2"-->{{y}}"#;
+        assert_eq!(compile(source), "1 1 2");
+    }
+
+    #[test]
+    fn interpolation_escapes_outside_scripts() {
+        let source = r#"<!--?set name="v" value="a<b"--><p title="{{v}}">{{v}}</p><script>{{v}}</script>"#;
+        assert_eq!(compile(source), r#"<p title="a&lt;b">a&lt;b</p><script>a<b</script>"#);
+        let source = r#"<!--?set name="v" value="a<b"--><!--?var name="v"--><style><!--?var name="v"--></style>"#;
+        assert_eq!(compile(source), "a&lt;b<style>a<b</style>");
+    }
 }
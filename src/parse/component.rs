@@ -1,27 +1,44 @@
 use std::fmt::{Display, Formatter};
 use std::{fmt, mem};
 use crate::parse::doc::Doc;
+use crate::parse::span::Span;
 use crate::parse::tag::{BinaryTag, Tag};
 
-#[derive(Debug)]
+/// A component parsed from bytes, referring to its contents by their spans in the source.
+#[derive(Debug, Clone)]
 pub enum BinaryComponent {
-    Text(Vec<u8>),
-    Comment(Vec<u8>),
-    CustomComment(Vec<u8>),
+    Text(Span),
+    Comment(Span),
+    CustomComment(Span),
     Tag(BinaryTag),
-    CustomTag(BinaryTag),
-    DocType(Vec<u8>),
-    Pointer(Doc),
-    Null,
+    /// A custom tag with the span of its source, between `<!--?` and `-->`.
+    CustomTag(BinaryTag, Span),
+    DocType(Span),
+}
+
+impl BinaryComponent {
+    /// Copies the component out of its source, replacing bytes that are not UTF-8.
+    /// Comments are dropped, as they are not part of the output.
+    pub fn into_component(self, source: &[u8]) -> Option<Component> {
+        Some(match self {
+            Self::Text(span) => Component::Text(span.to_string_lossy(source)),
+            Self::Comment(_) => return None,
+            Self::CustomComment(span) => Component::CustomComment(span.to_string_lossy(source)),
+            Self::Tag(tag) => Component::Tag(Tag::from_binary(&tag, source)),
+            Self::CustomTag(tag, span) => Component::CustomTag(Tag::from_binary(&tag, source), span.to_string_lossy(source), None),
+            Self::DocType(span) => Component::DocType(span.to_string_lossy(source)),
+        })
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Component {
     Text(String),
     Comment(String),
     CustomComment(String),
     Tag(Tag),
-    CustomTag(Tag, String),
+    /// A custom tag with its source and, if it is paired with a closing tag, the enclosed components.
+    CustomTag(Tag, String, Option<Doc>),
     DocType(String),
     Pointer(Doc),
     Null,
@@ -50,9 +67,12 @@ impl Display for Component {
             Self::Tag(v) => {
                 write!(fmt, "<{}>", v)
             }
-            Self::CustomTag(_, v) => {
+            Self::CustomTag(_, v, None) => {
                 write!(fmt, "<!--?{}-->", v)
             }
+            Self::CustomTag(tag, v, Some(body)) => {
+                write!(fmt, "<!--?{}-->{}<!--?/{}-->", v, body, tag.tag())
+            }
             Self::DocType(v) => {
                 write!(fmt, "<!{}>", v)
             }
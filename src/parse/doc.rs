@@ -1,212 +1,267 @@
 use std::collections::VecDeque;
 use std::fmt::{Display, Formatter};
-use std::ops::{Index, IndexMut, RangeBounds};
+use std::ops::{Index, IndexMut};
 use std::rc::Rc;
-use std::str;
-use crate::parse::tag::{BinaryTag, Tag};
+use crate::data;
+use crate::error::{Error, ErrorKind, Result};
+use crate::parse::tag::{BinaryTag, RAW_TEXT_ELEMENTS, Tag, TagKind};
 use crate::parse::component::{BinaryComponent, Component};
+use crate::parse::span::Span;
 use crate::source::SourceManager;
-use crate::util;
 
+/// A flat list of components parsed from bytes, each with the span it was parsed from.
+/// Nothing is copied out of the source; `Doc::new` does that once per component.
 #[derive(Debug)]
 pub struct BinaryDoc {
-    doc: VecDeque<BinaryComponent>,
+    doc: VecDeque<(Span, BinaryComponent)>,
+    /// Problems that do not stop the parsing, e.g. duplicate attributes.
+    warnings: Vec<Error>,
 }
 
 impl BinaryDoc {
-    pub fn new(source: Rc<String>) -> Option<Self> {
+    /// Parses a document. Spans of components and errors are byte ranges in `source`.
+    pub fn new(source: &[u8]) -> Result<Self> {
         let mut doc = Self {
             doc: VecDeque::new(),
+            warnings: Vec::new(),
         };
-        let mut source = SourceManager::new(&*source);
+        let mut source = SourceManager::new(source);
         while doc.push_text_and_next(&mut source) {
-            if source.pop_if_starts_with(b"!--?") {
-                doc.push_custom_tag(&mut source)?;
-            } else if source.pop_if_starts_with(b"!--") {
-                doc.push_comment(&mut source)?;
-            } else if source.pop_if_starts_with(b"!") {
-                doc.push_doc_type(&mut source)?;
+            let offset = source.offset();
+            if source.starts_with(b"<!--?") {
+                doc.push_custom(&mut source)?;
+            } else if source.pop_if_starts_with(b"<!--") {
+                let end = source.find(b"-->").ok_or_else(|| unclosed("comment", offset))?;
+                doc.push(Span::new(offset, end + 3), BinaryComponent::Comment(Span::new(source.offset(), end)));
+                source.advance(end + 3 - source.offset());
+            } else if source.pop_if_starts_with(b"<!") {
+                let end = source.find(b">").ok_or_else(|| unclosed("doctype", offset))?;
+                doc.push(Span::new(offset, end + 1), BinaryComponent::DocType(Span::new(source.offset(), end)));
+                source.advance(end + 1 - source.offset());
+            } else if !source.peek_at(1).is_some_and(|b| b.is_ascii_alphabetic() || b == b'/') {
+                doc.push(Span::new(offset, offset + 1), BinaryComponent::Text(Span::new(offset, offset + 1)));
+                source.advance(1);
             } else {
                 doc.push_tag(&mut source)?;
             }
         }
-        Some(doc)
+        Ok(doc)
     }
+    /// Pushes the text up to the next `<`, leaving the cursor on it. Returns false at the end of the source.
     fn push_text_and_next(&mut self, source: &mut SourceManager) -> bool {
+        let start = source.offset();
         let res = source.next_at_first_of(b"<");
-        let text = source.partially_to_vec();
-        if !text.is_empty() {
-            self.push(BinaryComponent::Text(text));
-        }
-        if res {
-            source.move_to_next();
+        let end = source.partial_end();
+        if end > start {
+            self.push(Span::new(start, end), BinaryComponent::Text(Span::new(start, end)));
         }
+        source.advance(end - start);
         res
     }
-    fn push_custom_tag(&mut self, source: &mut SourceManager) -> Option<()> {
-        if !source.next_at_first_of(b"-->") {
-            return None
-        }
-        if let Some(tag) = BinaryTag::new_custom(source.partially_from()) {
-            self.push(BinaryComponent::CustomTag(tag));
+    /// Pushes a custom tag, or a custom comment if it is front matter.
+    fn push_custom(&mut self, source: &mut SourceManager) -> Result<()> {
+        let offset = source.offset();
+        let end = source.find(b"-->").ok_or_else(|| unclosed("comment", offset))?;
+        source.advance("<!--?".len());
+        let span = Span::new(offset, end + 3);
+        let inside = Span::new(source.offset(), end);
+        if data::is_front_matter(&source.source()[inside.start..inside.end]) {
+            self.push(span, BinaryComponent::CustomComment(inside));
         } else {
-            self.push(BinaryComponent::CustomComment(source.partially_to_vec()));
+            let tag = BinaryTag::new_custom(source, end, span)?;
+            self.warn_attributes(&tag, source);
+            self.push(span, BinaryComponent::CustomTag(tag, inside));
         }
-        source.move_to_next();
-        Some(())
+        source.advance(end + 3 - source.offset());
+        Ok(())
     }
-    fn push_comment(&mut self, source: &mut SourceManager) -> Option<()> {
-        if !source.next_at_first_of(b"-->") {
-            return None
+    /// Pushes a tag and, if it starts a raw text element, its content up to the end tag.
+    /// As in HTML, a `/` at the end of the start tag, e.g. `<script/>`, does not end the element.
+    fn push_tag(&mut self, source: &mut SourceManager) -> Result<()> {
+        let tag = BinaryTag::new(source)?;
+        let span = tag.span();
+        let name = &source.source()[tag.tag().start..tag.tag().end];
+        let raw_text = RAW_TEXT_ELEMENTS.iter()
+            .find(|element| tag.kind() != TagKind::End && name.eq_ignore_ascii_case(element.as_bytes()));
+        self.warn_attributes(&tag, source);
+        self.push(span, BinaryComponent::Tag(tag));
+        if let Some(name) = raw_text {
+            let end = Self::raw_text_end(source, name)
+                .ok_or_else(|| Error::new(ErrorKind::UnmatchedTag(name.to_string())).at(span))?;
+            self.push_raw_text(source, end)?;
         }
-        let comment = source.partially_to_vec();
-        self.push(BinaryComponent::Comment(comment));
-        source.move_to_next();
-        Some(())
-    }
-    fn push_doc_type(&mut self, source: &mut SourceManager) -> Option<()> {
-        if !source.next_at_first_of(b">") {
-            return None
+        Ok(())
+    }
+    /// Finds the end tag of a raw text element, e.g. `</script>`, in any case.
+    fn raw_text_end(source: &SourceManager, name: &str) -> Option<usize> {
+        let rest = source.rest();
+        (0..rest.len()).filter(|&idx| rest[idx..].starts_with(b"</")).find(|&idx| {
+            let after = &rest[idx + 2..];
+            after.len() >= name.len()
+                && after[..name.len()].eq_ignore_ascii_case(name.as_bytes())
+                && after.get(name.len()).is_none_or(|b| b.is_ascii_whitespace() || *b == b'>' || *b == b'/')
+        }).map(|idx| source.offset() + idx)
+    }
+    /// Pushes the content of a raw text element up to `end` as text, except for the custom tags in it.
+    fn push_raw_text(&mut self, source: &mut SourceManager, end: usize) -> Result<()> {
+        while source.offset() < end {
+            let start = source.offset();
+            let custom = source.find(b"<!--?").filter(|&idx| idx < end).unwrap_or(end);
+            if custom > start {
+                self.push(Span::new(start, custom), BinaryComponent::Text(Span::new(start, custom)));
+                source.advance(custom - start);
+            }
+            if custom < end {
+                self.push_custom(source)?;
+            }
+        }
+        Ok(())
+    }
+    /// Warns about the attributes of a tag that were parsed leniently.
+    fn warn_attributes(&mut self, tag: &BinaryTag, source: &SourceManager) {
+        for (key, value) in tag.duplicates() {
+            let name = key.to_string_lossy(source.source());
+            self.warnings.push(Error::new(ErrorKind::DuplicateAttribute(name))
+                .at(Span::new(key.start, value.end.max(key.end))));
         }
-        let doc_type = source.partially_to_vec();
-        self.push(BinaryComponent::DocType(doc_type));
-        source.move_to_next();
-        Some(())
-    }
-    fn push_tag(&mut self, source: &mut SourceManager) -> Option<()> {
-        if !source.next_at_first_of(b">") {
-            return None
+        for key in tag.no_values() {
+            let name = key.to_string_lossy(source.source());
+            self.warnings.push(Error::new(ErrorKind::NoAttributeValue(name)).at(*key));
         }
-        let tag = BinaryTag::new(source)?;
-        self.push(BinaryComponent::Tag(tag));
-        Some(())
     }
-    fn push(&mut self, component: BinaryComponent) {
-        self.doc.push_back(component);
+    fn push(&mut self, span: Span, component: BinaryComponent) {
+        self.doc.push_back((span, component));
     }
 }
 
-#[derive(Debug)]
+fn unclosed(what: &'static str, offset: usize) -> Error {
+    Error::new(ErrorKind::Unclosed(what)).at(Span::new(offset, offset + 1))
+}
+
+/// A flat list of components, each with the span it was parsed from.
+#[derive(Debug, Clone)]
 pub struct Doc {
-    doc: VecDeque<Component>,
+    doc: VecDeque<(Span, Component)>,
 }
 
 impl Doc {
-    pub fn new(doc: Rc<String>) -> Option<Self> {
-        let mut res = Vec::new();
-        let mut target = doc.as_str();
-        while let Some(next_tag) = Self::skip_text(target, &mut res) {
-            target = next_tag;
-            if next_tag.starts_with("<!--") {
-                let comment = Self::parse_comment(&mut target)?;
-                if let Component::CustomComment(tag_source) = comment {
-                    res.push(Self::parse_custom_tag(tag_source));
-                }
-                continue
-            }
-            if next_tag.starts_with("<!") {
-                let doc_type = Self::parse_doc_type(&mut target)?;
-                res.push(doc_type);
+    /// Parses a document, adding what is only warned about to `warnings`.
+    /// Spans of components, errors and warnings are byte ranges in `doc`.
+    pub fn new(doc: Rc<String>, warnings: &mut Vec<Error>) -> Result<Self> {
+        let source = doc.as_bytes();
+        let binary = BinaryDoc::new(source)?;
+        warnings.extend(binary.warnings);
+        let res = binary.doc.into_iter()
+            .filter_map(|(span, component)| Some((span, component.into_component(source)?)))
+            .collect();
+        Ok(Self {
+            doc: Self::pair_custom(res)?,
+        })
+    }
+    /// Moves the components between a custom tag and its closing tag, e.g. `<!--?/include-->`,
+    /// into the body of the custom tag.
+    fn pair_custom(flat: Vec<(Span, Component)>) -> Result<VecDeque<(Span, Component)>> {
+        let mut res: VecDeque<(Span, Component)> = VecDeque::new();
+        for (span, component) in flat {
+            let Component::CustomTag(tag, _, _) = &component else {
+                res.push_back((span, component));
                 continue
-            }
-            if next_tag.starts_with("<") {
-                let tag = Self::parse_tag(&mut target)?;
-                res.push(tag);
+            };
+            if tag.kind() != TagKind::End {
+                res.push_back((span, component));
                 continue
             }
+            let name = tag.tag();
+            let open = res.iter().rposition(|(_, c)| {
+                matches!(c, Component::CustomTag(tag, _, None) if tag.is_start() && tag.tag() == name)
+            }).ok_or_else(|| Error::new(ErrorKind::UnmatchedTag(format!("!--?/{}--", name))).at(span))?;
+            let body = res.split_off(open + 1);
+            let (open_span, Component::CustomTag(_, _, dest)) = &mut res[open] else { unreachable!() };
+            *open_span = Span::new(open_span.start, span.end);
+            *dest = Some(Self {
+                doc: body,
+            });
         }
-        Some(Self {
-            doc: res.into(),
-        })
+        Ok(res)
     }
-    fn skip_text<'a, 'b>(target: &'a str, dest: &'b mut Vec<Component>) -> Option<&'a str> {
-        let bytes = target.as_bytes();
-        let idx = util::first_of(bytes, b'<', 0);
-        if idx == bytes.len() {
-            let text = String::from(target);
-            dest.push(Component::Text(text));
-            return None
-        }
-        if idx > 0 {
-            let text = String::from(str::from_utf8(&bytes[..idx]).unwrap());
-            dest.push(Component::Text(text));
-        }
-        Some(str::from_utf8(&bytes[idx..]).unwrap())
-    }
-    fn parse_comment(target: &mut &str) -> Option<Component> {
-        debug_assert!(target.starts_with("<!--"));
-        let Some((_, other)) = target.split_once("<!--")
-            else { return None };
-        let Some((content, other)) = other.split_once("-->")
-            else { return None };
-        *target = other;
-        Some(Self::create_comment(content))
-    }
-    fn create_comment(content: &str) -> Component {
-        if content.starts_with("?") {
-            let Some((_, value)) = content.split_once("?")
-                else { unreachable!() };
-            let value_owned = String::from(value);
-            Component::CustomComment(value_owned)
-        } else {
-            let value_owned = String::from(content);
-            Component::Comment(value_owned)
+    pub fn empty() -> Self {
+        Self {
+            doc: VecDeque::new(),
         }
     }
-    fn parse_custom_tag(content: String) -> Component {
-        match Tag::new(content.as_str()) {
-            Some(tag) => Component::CustomTag(tag, content),
-            None => Component::CustomComment(content),
+    /// Creates a document of a single text, e.g. the value of a custom tag.
+    pub fn from_text(text: String) -> Self {
+        Self {
+            doc: VecDeque::from([(Span::default(), Component::Text(text))]),
         }
     }
-    fn parse_doc_type(target: &mut &str) -> Option<Component> {
-        debug_assert!(target.starts_with("<!"));
-        let Some((_, other)) = target.split_once("<!")
-            else { return None };
-        let Some((content, other)) = other.split_once(">")
-            else { return None };
-        *target = other;
-        Some(Component::DocType(String::from(content)))
-    }
-    fn parse_tag(target: &mut &str) -> Option<Component> {
-        debug_assert!(target.starts_with("<"));
-        let tag = Tag::new_once(target)?;
-        Some(Component::Tag(tag))
-    }
-    pub fn extract<R: RangeBounds<usize>>(&mut self, range: R) {
-        let mut triggered = false;
-        let len = self.doc.len();
-        for i in 0..len {
-            if !range.contains(&i) {
-                if triggered {
-                    self.doc.pop_back();
-                } else {
-                    self.doc.pop_front();
-                }
-            } else {
-                triggered = true;
+    /// Removes the top-level custom tags named `name` and returns them with their bodies.
+    pub fn take_custom(&mut self, name: &str) -> Vec<(Tag, Option<Doc>)> {
+        let mut res = Vec::new();
+        let mut rest = VecDeque::new();
+        for (span, component) in self.doc.drain(..) {
+            match component {
+                Component::CustomTag(tag, _, body) if tag.tag() == name => res.push((tag, body)),
+                component => rest.push_back((span, component)),
             }
         }
+        self.doc = rest;
+        res
     }
-    pub fn find_tags(&self, tag_name: &str) -> Vec<usize> {
-        let mut vec = Vec::new();
-        let len = self.doc.len();
-        for i in 0..len {
-            let Component::Tag(tag) = &self[i] else { continue };
-            if tag.tag() == tag_name {
-                vec.push(i);
-            }
+    pub fn len(&self) -> usize {
+        self.doc.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.doc.is_empty()
+    }
+    /// Splits the document in two at `at`, keeping the components before it.
+    pub fn split_off(&mut self, at: usize) -> Doc {
+        Self {
+            doc: self.doc.split_off(at),
         }
-        vec
-    }
-    pub fn reassign_custom<F: FnMut(Tag) -> Component>(&mut self, mut func: F) {
-        let len = self.doc.len();
-        for i in 0..len {
-            if let Component::CustomTag(_, _) = &self[i] {
-                let Component::CustomTag(tag, _) = self[i].swap_null() else { unreachable!() };
-                self[i] = func(tag);
+    }
+    /// Moves the components of `other` to the end of the document.
+    pub fn append(&mut self, mut other: Doc) {
+        self.doc.append(&mut other.doc);
+    }
+    /// Replaces the expanded custom tags with their contents, recursively.
+    pub fn flatten(self) -> Doc {
+        let mut res = Self::empty();
+        for (span, component) in self.doc {
+            match component {
+                Component::Pointer(doc) => res.append(doc.flatten()),
+                Component::Null => {}
+                component => res.push(span, component),
             }
         }
+        res
+    }
+    pub fn push(&mut self, span: Span, component: Component) {
+        self.doc.push_back((span, component));
+    }
+    /// Iterates over the components with their spans.
+    pub fn into_components(self) -> impl Iterator<Item = (Span, Component)> {
+        self.doc.into_iter()
+    }
+    /// Inserts a document before the component at `index`.
+    pub fn insert(&mut self, index: usize, doc: Doc) {
+        self.doc.insert(index, (Span::default(), Component::Pointer(doc)));
+    }
+    /// Finds the first top-level custom tag named `name`.
+    pub fn position_custom(&self, name: &str) -> Option<usize> {
+        self.doc.iter().position(|(_, c)| matches!(c, Component::CustomTag(tag, _, _) if tag.tag() == name))
+    }
+    /// Removes the component at `index`.
+    pub fn remove(&mut self, index: usize) -> Option<Component> {
+        self.doc.remove(index).map(|(_, component)| component)
+    }
+    /// Whether the document has only whitespace text.
+    pub fn is_blank(&self) -> bool {
+        self.doc.iter().all(|(_, c)| matches!(c, Component::Text(text) if text.trim().is_empty()))
+    }
+    /// The span in the source file of the component at `index`.
+    pub fn span(&self, index: usize) -> Span {
+        self.doc[index].0
     }
 }
 
@@ -214,19 +269,19 @@ impl Index<usize> for Doc {
     type Output = Component;
 
     fn index(&self, index: usize) -> &Self::Output {
-        self.doc.index(index)
+        &self.doc.index(index).1
     }
 }
 
 impl IndexMut<usize> for Doc {
     fn index_mut(&mut self, index: usize) -> &mut Self::Output {
-        self.doc.index_mut(index)
+        &mut self.doc.index_mut(index).1
     }
 }
 
 impl Display for Doc {
     fn fmt(&self, fmt: &mut Formatter<'_>) -> std::fmt::Result {
-        for token in &self.doc {
+        for (_, token) in &self.doc {
             write!(fmt, "{}", token)?;
         }
         Ok(())
@@ -0,0 +1,162 @@
+use std::fmt::{Display, Formatter};
+use std::fmt;
+use crate::parse::tag::Tag;
+use crate::util;
+
+/// A simple selector of elements: `section`, `#pricing`, `.card`, `div.card#main`
+/// and `section:nth-of-type(2)`, where, as in CSS, the position is among the siblings
+/// with the same tag name, 1-based.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Selector {
+    tag: Option<String>,
+    id: Option<String>,
+    classes: Vec<String>,
+    nth: Option<usize>,
+}
+
+impl Selector {
+    /// Parses a selector, returning `None` if it is empty or malformed.
+    pub fn parse(source: &str) -> Option<Self> {
+        let source = source.trim();
+        let (simple, nth) = match source.split_once(':') {
+            Some((simple, pseudo)) => {
+                let n = pseudo.strip_prefix("nth-of-type(")?.strip_suffix(')')?;
+                (simple, Some(n.trim().parse::<usize>().ok().filter(|n| *n > 0)?))
+            }
+            None => (source, None),
+        };
+        let mut res = Self {
+            nth,
+            ..Self::default()
+        };
+        let is_name = |c: char| c.is_ascii_alphanumeric() || c == '-' || c == '_';
+        let mut rest = simple;
+        let tag_len = rest.find(|c: char| !is_name(c)).unwrap_or(rest.len());
+        if tag_len > 0 {
+            res.tag = Some(rest[..tag_len].to_ascii_lowercase());
+        }
+        rest = &rest[tag_len..];
+        while let Some(prefix) = rest.chars().next() {
+            let name_len = rest[1..].find(|c: char| !is_name(c)).unwrap_or(rest.len() - 1);
+            if name_len == 0 {
+                return None
+            }
+            let name = rest[1..1 + name_len].to_string();
+            match prefix {
+                '#' if res.id.is_none() => res.id = Some(name),
+                '.' => res.classes.push(name),
+                _ => return None,
+            }
+            rest = &rest[1 + name_len..];
+        }
+        if res == Self::default() {
+            return None
+        }
+        Some(res)
+    }
+    /// A selector of elements by tag name only, e.g. for `part="head"`.
+    pub fn tag(name: &str) -> Self {
+        Self {
+            tag: Some(name.to_ascii_lowercase()),
+            ..Self::default()
+        }
+    }
+    /// The position among the siblings with the same tag name, 1-based, if the selector has one.
+    pub fn nth(&self) -> Option<usize> {
+        self.nth
+    }
+    /// Whether a start tag matches the selector, ignoring the position.
+    pub fn matches(&self, tag: &Tag) -> bool {
+        if !tag.is_start() || self.tag.as_ref().is_some_and(|t| !t.eq_ignore_ascii_case(tag.tag())) {
+            return false
+        }
+        let attribute = |key: &str| tag.attributes()
+            .find(|(k, _)| k.eq_ignore_ascii_case(key))
+            .map(|(_, v)| util::unquote(v).to_string());
+        if let Some(id) = &self.id {
+            if attribute("id").as_ref() != Some(id) {
+                return false
+            }
+        }
+        if !self.classes.is_empty() {
+            let class = attribute("class").unwrap_or_default();
+            let class: Vec<_> = class.split_whitespace().collect();
+            if !self.classes.iter().all(|c| class.contains(&c.as_str())) {
+                return false
+            }
+        }
+        true
+    }
+}
+
+impl Display for Selector {
+    fn fmt(&self, fmt: &mut Formatter<'_>) -> fmt::Result {
+        if let Some(tag) = &self.tag {
+            write!(fmt, "{}", tag)?;
+        }
+        if let Some(id) = &self.id {
+            write!(fmt, "#{}", id)?;
+        }
+        for class in &self.classes {
+            write!(fmt, ".{}", class)?;
+        }
+        if let Some(nth) = self.nth {
+            write!(fmt, ":nth-of-type({})", nth)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+    use super::*;
+    use crate::parse::component::Component;
+    use crate::parse::doc::Doc;
+
+    fn tag(source: &str) -> Tag {
+        let doc = Doc::new(Rc::new(source.to_string()), &mut Vec::new()).unwrap();
+        let Component::Tag(tag) = &doc[0] else { panic!("{} is not a tag", source) };
+        tag.clone()
+    }
+
+    fn round_trip(source: &str) -> Option<String> {
+        Selector::parse(source).map(|selector| selector.to_string())
+    }
+
+    #[test]
+    fn simple_selectors() {
+        assert_eq!(round_trip("section").as_deref(), Some("section"));
+        assert_eq!(round_trip("#pricing").as_deref(), Some("#pricing"));
+        assert_eq!(round_trip(".card").as_deref(), Some(".card"));
+        assert_eq!(round_trip("div.card#main").as_deref(), Some("div#main.card"));
+        assert_eq!(round_trip(" DIV.a.b ").as_deref(), Some("div.a.b"));
+        assert_eq!(Selector::parse("head"), Some(Selector::tag("head")));
+    }
+
+    #[test]
+    fn positions() {
+        let selector = Selector::parse("section:nth-of-type(2)").unwrap();
+        assert_eq!(selector.nth(), Some(2));
+        assert_eq!(selector.to_string(), "section:nth-of-type(2)");
+        assert_eq!(Selector::parse(".card:nth-of-type(1)").unwrap().nth(), Some(1));
+        assert_eq!(Selector::parse("section").unwrap().nth(), None);
+    }
+
+    #[test]
+    fn malformed_selectors() {
+        for source in ["", "#", ".", "a b", "#a#b", "a:hover", "a:nth-of-type(0)", "a:nth-of-type(x)", "a>b"] {
+            assert_eq!(Selector::parse(source), None, "{}", source);
+        }
+    }
+
+    #[test]
+    fn matching_start_tags() {
+        let selector = Selector::parse("div.card#main").unwrap();
+        assert!(selector.matches(&tag("<DIV class='big card' ID=\"main\">")));
+        assert!(!selector.matches(&tag("<div class=card>")));
+        assert!(!selector.matches(&tag("<section class=card id=main>")));
+        assert!(!Selector::tag("div").matches(&tag("</div>")));
+        assert!(Selector::parse(".a.b").unwrap().matches(&tag("<p class=\"b  a\">")));
+    }
+}
@@ -0,0 +1,49 @@
+/// A byte range in a source file.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub const fn new(start: usize, end: usize) -> Self {
+        Self {
+            start,
+            end,
+        }
+    }
+    /// Copies the bytes of the span out of `source`, replacing those that are not UTF-8.
+    pub fn to_string_lossy(&self, source: &[u8]) -> String {
+        String::from_utf8_lossy(&source[self.start..self.end]).into_owned()
+    }
+    /// Finds the 1-based line and column (in characters) where the span starts.
+    pub fn line_col(&self, source: &str) -> (usize, usize) {
+        let start = self.start.min(source.len());
+        let before = &source[..floor_char_boundary(source, start)];
+        let line = before.matches('\n').count() + 1;
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        (line, before[line_start..].chars().count() + 1)
+    }
+    /// Renders the line containing the span with the span underlined by carets.
+    pub fn excerpt(&self, source: &str) -> String {
+        let start = floor_char_boundary(source, self.start.min(source.len()));
+        let line_start = source[..start].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = source[start..].find('\n').map_or(source.len(), |i| start + i);
+        let line = source[line_start..line_end].trim_end_matches('\r');
+        let end = floor_char_boundary(source, self.end.clamp(start, line_start + line.len()));
+        let (line_no, _) = self.line_col(source);
+        let gutter = line_no.to_string().len();
+        let indent: String = source[line_start..start].chars()
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        let width = source[start..end].chars().count().max(1);
+        format!("{:>gutter$} | {}\n{:>gutter$} | {}{}", line_no, line, "", indent, "^".repeat(width))
+    }
+}
+
+fn floor_char_boundary(source: &str, mut idx: usize) -> usize {
+    while !source.is_char_boundary(idx) {
+        idx -= 1;
+    }
+    idx
+}
@@ -1,134 +1,373 @@
 use std::fmt::{Display, Formatter};
-use std::{fmt, mem, str};
+use std::fmt;
+use crate::error::{Error, ErrorKind, Result};
+use crate::parse::span::Span;
 use crate::source::SourceManager;
-use crate::util;
 use crate::util::VecDict;
 
-#[derive(Debug)]
-pub struct BinaryTag {
-    tag: Vec<u8>,
-    attributes: VecDict<Vec<u8>, Vec<u8>>,
-}
+/// Elements that never have an end tag.
+pub const VOID_ELEMENTS: [&str; 14] = [
+    "area", "base", "br", "col", "embed", "hr", "img",
+    "input", "link", "meta", "param", "source", "track", "wbr",
+];
 
-impl BinaryTag {
-    pub fn new(source: &mut SourceManager) -> Option<Self> {
-        // todo
-        None
-    }
-    pub fn new_custom(source: SourceManager) -> Option<Self> {
-        // todo
-        None
-    }
+/// Elements whose content is text up to their end tag, apart from custom tags.
+pub const RAW_TEXT_ELEMENTS: [&str; 4] = ["script", "style", "textarea", "title"];
+
+/// Whether a tag opens an element, closes it, or is a whole element by itself.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TagKind {
+    /// `<p>`
+    Start,
+    /// `</p>`
+    End,
+    /// `<br/>`
+    SelfClosing,
 }
 
-#[derive(Debug)]
-pub struct Tag {
-    tag: String,
-    attributes: VecDict<String, String>,
+/// A tag parsed from bytes. It refers to its name and attributes by their spans in the source
+/// instead of copying them; `Tag::from_binary` copies them out.
+#[derive(Debug, Clone)]
+pub struct BinaryTag {
+    tag: Span,
+    kind: TagKind,
+    custom: bool,
+    /// The spans of the keys and the raw values. The value of an attribute without `=` is empty.
+    attributes: Vec<(Span, Span)>,
+    /// The attributes left out because an earlier one has the same key.
+    duplicates: Vec<(Span, Span)>,
+    /// The keys of the attributes with `=` but no value, which are kept with an empty value.
+    no_values: Vec<Span>,
+    span: Span,
 }
 
-impl Tag {
-    pub fn new(str_inside: &str) -> Option<Self> {
-        let (tag, raw_attr) = str_inside.split_once(' ')
-            .unwrap_or((str_inside, ""));
+impl BinaryTag {
+    /// Parses a tag at the cursor, which is on its `<`, and moves the cursor past its `>`.
+    pub fn new(source: &mut SourceManager) -> Result<Self> {
+        let start = source.offset();
+        let unclosed = || Error::new(ErrorKind::Unclosed("tag")).at(Span::new(start, start + 1));
+        if !source.pop_if_starts_with(b"<") {
+            return Err(unclosed())
+        }
+        let kind = if source.pop_if_starts_with(b"/") { TagKind::End } else { TagKind::Start };
+        let name_start = source.offset();
+        source.skip_while(|b| !is_space(b) && b != b'>' && b != b'/');
         let mut res = Self {
-            tag: String::from(tag),
-            attributes: VecDict::new(),
+            tag: Span::new(name_start, source.offset()),
+            kind,
+            custom: false,
+            attributes: Vec::new(),
+            duplicates: Vec::new(),
+            no_values: Vec::new(),
+            span: Span::default(),
         };
-        let attributes = raw_attr.as_bytes();
-        let len = attributes.len();
-        let mut idx = util::first_not_of(attributes, b' ', 0);
-        while idx < len {
-            idx = res.next_attribute(attributes, idx)?;
+        res.parse_attributes(source, usize::MAX, true)?;
+        if !source.pop_if_starts_with(b">") {
+            return Err(unclosed())
         }
-        Some(res)
+        res.span = Span::new(start, source.offset());
+        Ok(res)
     }
-    pub fn new_once(str_all: &mut &str) -> Option<Self> {
-        if !str_all.starts_with("<") {
-            return None
-        }
-        (_, *str_all) = str_all.split_once("<").unwrap();
-        let (tag, raw_attr) = str_all.split_once('>')?;
-        if !tag.contains(' ') {
-            *str_all = raw_attr;
-            return Self::new(tag)
+    /// Parses the inside of a custom tag from the cursor up to `end`, e.g. between `<!--?` and `-->`,
+    /// and moves the cursor to `end`. `span` is the span of the whole custom tag.
+    pub fn new_custom(source: &mut SourceManager, end: usize, span: Span) -> Result<Self> {
+        let kind = if source.pop_if_starts_with(b"/") { TagKind::End } else { TagKind::Start };
+        let name_start = source.offset();
+        while source.offset() < end && source.peek().is_some_and(|b| !is_space(b)) {
+            source.advance(1);
         }
-        let (tag, raw_attr) = str_all.split_once(' ').unwrap();
         let mut res = Self {
-            tag: String::from(tag),
-            attributes: VecDict::new(),
+            tag: Span::new(name_start, source.offset()),
+            kind,
+            custom: true,
+            attributes: Vec::new(),
+            duplicates: Vec::new(),
+            no_values: Vec::new(),
+            span,
         };
-        let attributes = raw_attr.as_bytes();
-        let len = attributes.len();
-        let mut idx = util::first_not_of(attributes, b' ', 0);
-        while idx < len {
-            if attributes[idx] == b'>' {
-                *str_all = str::from_utf8(&attributes[idx + 1..]).unwrap();
-                return Some(res)
+        res.parse_attributes(source, end, false)?;
+        Ok(res)
+    }
+    /// Tokenizes attributes by the HTML5 rules: names without values, values in double quotes,
+    /// single quotes or none, whitespace around `=`, and a `/` before the end of the tag.
+    /// Stops before `limit`, the end of the source, or a `>` ending the tag if `until_gt`.
+    fn parse_attributes(&mut self, source: &mut SourceManager, limit: usize, until_gt: bool) -> Result<()> {
+        let peek = |source: &SourceManager| source.peek().filter(|_| source.offset() < limit);
+        loop {
+            while let Some(b) = peek(source).filter(|b| is_space(*b) || *b == b'/') {
+                if until_gt && b == b'/' && source.peek_at(1) == Some(b'>') && self.kind == TagKind::Start {
+                    self.kind = TagKind::SelfClosing;
+                }
+                source.advance(1);
             }
-            idx = res.next_attribute(attributes, idx)?;
-        }
-        None
-    }
-    fn next_attribute(&mut self, slice: &[u8], from: usize) -> Option<usize> {
-        let len = slice.len();
-        let eq = util::first_of(slice, b'=', from);
-        let key = str::from_utf8(&slice[from..eq]).unwrap();
-        if eq == len {
-            eprintln!("[ERROR] There is no separator of an attribute for key: {}", key);
-            return None
-        }
-        if eq + 1 == len {
-            eprintln!("[ERROR] There is no value of an attribute for key: {}", key);
-            return None
-        }
-        let to = self.end_of_value(slice, eq + 1, key)?;
-        let value = str::from_utf8(&slice[eq + 1..to]).unwrap();
-        self.push_attribute(String::from(key), String::from(value));
-        Some(util::first_not_of(slice, b' ', to))
-    }
-    fn end_of_value(&mut self, slice: &[u8], from: usize, key: &str) -> Option<usize> {
-        let len = slice.len();
-        if slice[from] == b'"' {
-            let dq = util::first_of(slice, b'"', from + 1);
-            if dq == len {
-                eprintln!("[ERROR] There is no value of an attribute for key: {}", key);
-                return None
+            match peek(source) {
+                None => return Ok(()),
+                Some(b'>') if until_gt => return Ok(()),
+                _ => {}
             }
-            Some(dq + 1)
-        } else if slice[from] == b' ' {
-            eprintln!("[ERROR] There is no value of an attribute for key: {}", key);
-            None
-        } else {
-            Some(util::first_of(slice, b' ', from))
+            let name_start = source.offset();
+            source.advance(1);
+            while peek(source).is_some_and(|b| !is_space(b) && !b"/>=".contains(&b)) {
+                source.advance(1);
+            }
+            let key = Span::new(name_start, source.offset());
+            while peek(source).is_some_and(is_space) {
+                source.advance(1);
+            }
+            if peek(source) != Some(b'=') {
+                self.push_attribute(source, key, Span::new(key.end, key.end));
+                continue
+            }
+            source.advance(1);
+            while peek(source).is_some_and(is_space) {
+                source.advance(1);
+            }
+            let value_start = source.offset();
+            match peek(source) {
+                None | Some(b'>') => self.no_values.push(key),
+                Some(quote @ (b'"' | b'\'')) => {
+                    source.advance(1);
+                    while peek(source).is_some_and(|b| b != quote) {
+                        source.advance(1);
+                    }
+                    if peek(source).is_none() {
+                        return Err(Error::new(ErrorKind::Unclosed("attribute value"))
+                            .at(Span::new(value_start, value_start + 1)))
+                    }
+                    source.advance(1);
+                }
+                Some(_) => {
+                    while peek(source).is_some_and(|b| !(is_space(b) || until_gt && b == b'>')) {
+                        source.advance(1);
+                    }
+                }
+            }
+            self.push_attribute(source, key, Span::new(value_start, source.offset()));
         }
     }
-    fn push_attribute(&mut self, key: String, value: String) {
-        if !self.attributes.contains(&key) {
-            self.attributes.push_unique(key, String::from(value));
+    fn push_attribute(&mut self, source: &SourceManager, key: Span, value: Span) {
+        let bytes = source.source();
+        let name = &bytes[key.start..key.end];
+        if self.attributes.iter().any(|(other, _)| &bytes[other.start..other.end] == name) {
+            self.duplicates.push((key, value));
         } else {
-            eprintln!("[WARN] Duplicate attribute key found: {}", key);
+            self.attributes.push((key, value));
+        }
+    }
+    /// The span of the name, without the `/` of an end tag.
+    pub fn tag(&self) -> Span {
+        self.tag
+    }
+    pub fn kind(&self) -> TagKind {
+        self.kind
+    }
+    /// The span of the whole tag.
+    pub fn span(&self) -> Span {
+        self.span
+    }
+    /// The spans of the keys and values of the attributes left out because an earlier one has the same key.
+    pub fn duplicates(&self) -> &[(Span, Span)] {
+        &self.duplicates
+    }
+    /// The spans of the keys of the attributes with `=` but no value.
+    pub fn no_values(&self) -> &[Span] {
+        &self.no_values
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Tag {
+    /// The name, without the `/` of an end tag. Lowercase unless it names a custom tag.
+    tag: String,
+    kind: TagKind,
+    attributes: VecDict<String, (String, Span)>,
+    span: Span,
+}
+
+impl Tag {
+    /// Copies a tag parsed from bytes out of its source. Bytes that are not UTF-8 are replaced.
+    pub fn from_binary(binary: &BinaryTag, source: &[u8]) -> Self {
+        let name = binary.tag.to_string_lossy(source);
+        let mut res = Self {
+            tag: if binary.custom { name } else { name.to_ascii_lowercase() },
+            kind: binary.kind,
+            attributes: VecDict::new(),
+            span: binary.span,
+        };
+        for (key, value) in &binary.attributes {
+            let span = Span::new(key.start, value.end.max(key.end));
+            res.attributes.push_unique(key.to_string_lossy(source), (value.to_string_lossy(source), span));
         }
+        res
     }
     pub fn tag(&self) -> &str {
         &self.tag[..]
     }
+    pub fn kind(&self) -> TagKind {
+        self.kind
+    }
+    /// Whether the tag opens an element or is a whole element, as opposed to closing one.
+    pub fn is_start(&self) -> bool {
+        self.kind != TagKind::End
+    }
+    /// Whether the tag names an element that never has an end tag, e.g. `<br>`.
+    pub fn is_void(&self) -> bool {
+        VOID_ELEMENTS.contains(&self.tag.as_str())
+    }
+    /// Whether the tag opens an element that has an end tag, i.e. it is neither an end tag,
+    /// self-closing nor void. A raw text element is opened even if self-closing, as its content follows.
+    pub fn opens_element(&self) -> bool {
+        match self.kind {
+            TagKind::Start => !self.is_void(),
+            TagKind::SelfClosing => RAW_TEXT_ELEMENTS.contains(&self.tag.as_str()),
+            TagKind::End => false,
+        }
+    }
+    /// The span of the whole tag.
+    pub fn span(&self) -> Span {
+        self.span
+    }
+    /// The span of the attribute `key`, from the start of its key to the end of its value.
+    pub fn attribute_span(&self, key: &str) -> Option<Span> {
+        self.attributes.get(key).map(|(_, span)| *span)
+    }
+    /// Removes the attribute `key` and returns its raw value, quotes included.
+    /// The value of an attribute without `=`, e.g. `disabled`, is empty.
     pub fn consume(&mut self, key: &str) -> Option<String> {
-        let mut dest = String::new();
-        mem::swap(self.attributes.get_mut(key)?, &mut dest);
-        Some(dest)
+        self.attributes.remove(key).map(|(value, _)| value)
+    }
+    /// Iterates over the attributes in source order, with raw values.
+    pub fn attributes(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.attributes.iter().map(|(key, (value, _))| (key.as_str(), value.as_str()))
+    }
+    pub fn for_each_value_mut<F: FnMut(&str, &mut String)>(&mut self, mut func: F) {
+        self.attributes.for_each_mut(|key, (value, _)| func(key, value));
     }
 }
 
 impl Display for Tag {
     fn fmt(&self, fmt: &mut Formatter<'_>) -> fmt::Result {
-        let mut buf = self.tag.to_string();
-        self.attributes.for_each(|key, value| {
+        let mut buf = match self.kind {
+            TagKind::End => format!("/{}", self.tag),
+            _ => self.tag.to_string(),
+        };
+        self.attributes.for_each(|key, (value, _)| {
             buf += " ";
             buf += key;
-            buf += "=";
-            buf += value;
+            if !value.is_empty() {
+                buf += "=";
+                buf += value;
+            }
         });
+        if self.kind == TagKind::SelfClosing {
+            buf += "/";
+        }
         write!(fmt, "{}", buf)
     }
 }
+
+/// Whitespace between attributes, as defined by HTML.
+fn is_space(byte: u8) -> bool {
+    matches!(byte, b' ' | b'\t' | b'\n' | b'\r' | 0x0C)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(source: &str) -> Result<Tag> {
+        let mut manager = SourceManager::new(source);
+        let tag = BinaryTag::new(&mut manager)?;
+        assert_eq!(manager.offset(), tag.span().end);
+        Ok(Tag::from_binary(&tag, source.as_bytes()))
+    }
+
+    fn attributes(source: &str) -> Vec<(String, String)> {
+        parse(source).unwrap().attributes()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    fn pairs(expected: &[(&str, &str)]) -> Vec<(String, String)> {
+        expected.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect()
+    }
+
+    #[test]
+    fn attribute_without_value() {
+        let tag = parse("<input disabled>").unwrap();
+        assert_eq!(tag.tag(), "input");
+        assert_eq!(tag.kind(), TagKind::Start);
+        assert!(!tag.opens_element());
+        assert_eq!(attributes("<input disabled>"), pairs(&[("disabled", "")]));
+        assert_eq!(tag.to_string(), "input disabled");
+    }
+
+    #[test]
+    fn quoted_and_unquoted_values() {
+        assert_eq!(attributes("<p class='a b' id=\"x\" title=t>"),
+                   pairs(&[("class", "'a b'"), ("id", "\"x\""), ("title", "t")]));
+        assert_eq!(attributes("<p title=\"a>b\">"), pairs(&[("title", "\"a>b\"")]));
+    }
+
+    #[test]
+    fn spaces_around_equals() {
+        assert_eq!(attributes("<a href = \"b\" hidden>"), pairs(&[("href", "\"b\""), ("hidden", "")]));
+    }
+
+    #[test]
+    fn tabs_and_newlines() {
+        assert_eq!(attributes("<a\thref=x\n\ttitle=\"t\"\r\n>"), pairs(&[("href", "x"), ("title", "\"t\"")]));
+    }
+
+    #[test]
+    fn self_closing_and_end_tags() {
+        assert_eq!(parse("<br/>").unwrap().kind(), TagKind::SelfClosing);
+        assert_eq!(parse("<br />").unwrap().kind(), TagKind::SelfClosing);
+        assert_eq!(parse("<script/>").unwrap().kind(), TagKind::SelfClosing);
+        assert!(parse("<script/>").unwrap().opens_element());
+        let link = parse("<a href=/x/>").unwrap();
+        assert_eq!(link.kind(), TagKind::Start);
+        assert_eq!(attributes("<a href=/x/>"), pairs(&[("href", "/x/")]));
+        let end = parse("</P>").unwrap();
+        assert_eq!((end.tag(), end.kind()), ("p", TagKind::End));
+        assert_eq!(end.to_string(), "/p");
+    }
+
+    #[test]
+    fn duplicate_attributes_keep_the_first() {
+        let source = "<p class=a class=b>";
+        let mut manager = SourceManager::new(source);
+        let tag = BinaryTag::new(&mut manager).unwrap();
+        assert_eq!(tag.duplicates(), [(Span::new(11, 16), Span::new(17, 18))]);
+        assert_eq!(attributes(source), pairs(&[("class", "a")]));
+    }
+
+    #[test]
+    fn equals_without_a_value_keeps_an_empty_value() {
+        let source = "<p x= >";
+        let mut manager = SourceManager::new(source);
+        let tag = BinaryTag::new(&mut manager).unwrap();
+        assert_eq!(tag.no_values(), [Span::new(3, 4)]);
+        assert_eq!(attributes(source), pairs(&[("x", "")]));
+        assert_eq!(attributes("<p x= y=1>"), pairs(&[("x", "y=1")]));
+        assert_eq!(attributes("<p a=>"), pairs(&[("a", "")]));
+    }
+
+    #[test]
+    fn custom_tags_keep_the_case_of_their_name() {
+        let source = "<!--?Card title=\"T\"-->";
+        let mut manager = SourceManager::new(source);
+        manager.advance(5);
+        let tag = BinaryTag::new_custom(&mut manager, source.len() - 3, Span::new(0, source.len())).unwrap();
+        let tag = Tag::from_binary(&tag, source.as_bytes());
+        assert_eq!(tag.tag(), "Card");
+        assert_eq!(tag.attribute_span("title"), Some(Span::new(10, 19)));
+    }
+
+    #[test]
+    fn malformed_tags() {
+        let kind = |source| parse(source).unwrap_err().kind().to_string();
+        assert_eq!(kind("<p x=\"a>"), ErrorKind::Unclosed("attribute value").to_string());
+        assert_eq!(kind("<div"), ErrorKind::Unclosed("tag").to_string());
+    }
+}
@@ -0,0 +1,347 @@
+use crate::parse::component::Component;
+use crate::parse::doc::Doc;
+use crate::parse::select::Selector;
+use crate::parse::span::Span;
+use crate::parse::tag::{Tag, TagKind};
+
+/// A node of a `Tree`, valid as long as the tree it was taken from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NodeId(usize);
+
+#[derive(Debug, Clone)]
+pub enum Node {
+    /// The root, holding the top-level nodes.
+    Document,
+    /// An element with its start tag and its end tag, which is `None` if it is void,
+    /// self-closing, or its end is implied, e.g. `<li>` before the next `<li>`.
+    Element { start: Tag, end: Option<Tag> },
+    /// Anything else: text, comments, doctypes, custom tags, and end tags that close nothing.
+    Leaf(Component),
+}
+
+#[derive(Debug, Clone)]
+struct Entry {
+    node: Node,
+    span: Span,
+    parent: Option<NodeId>,
+    children: Vec<NodeId>,
+}
+
+/// Start tags that end open elements, with the elements they end and the elements that stop the search.
+/// E.g. `<li>` ends an open `<li>`, but not one outside the list it is in. A start tag may have several entries.
+const IMPLIED_ENDS: [(&[&str], &[&str], &[&str]); 9] = [
+    (&["body"], &["head"], &["html"]),
+    (&["address", "article", "aside", "blockquote", "details", "div", "dl", "fieldset", "figcaption",
+       "figure", "footer", "form", "h1", "h2", "h3", "h4", "h5", "h6", "header", "hgroup", "hr",
+       "main", "menu", "nav", "ol", "p", "pre", "section", "table", "ul", "li", "dd", "dt"],
+     &["p"],
+     &["button", "html", "body", "table", "td", "th", "caption", "template"]),
+    (&["li"], &["li"], &["ul", "ol", "menu", "table"]),
+    (&["dt", "dd"], &["dt", "dd"], &["dl", "table"]),
+    (&["option"], &["option"], &["select", "datalist", "optgroup"]),
+    (&["optgroup"], &["option", "optgroup"], &["select"]),
+    (&["tr"], &["tr"], &["table", "thead", "tbody", "tfoot"]),
+    (&["td", "th"], &["td", "th"], &["tr", "table"]),
+    (&["thead", "tbody", "tfoot"], &["thead", "tbody", "tfoot"], &["table"]),
+];
+
+/// A document as a tree of elements, built by HTML's rules for implied end tags.
+/// Rendering it gives back the markup it was built from.
+#[derive(Debug, Clone)]
+pub struct Tree {
+    nodes: Vec<Entry>,
+}
+
+impl Tree {
+    /// Builds the tree of a document. The contents of expanded custom tags become part of the tree.
+    pub fn new(doc: Doc) -> Self {
+        let mut res = Self {
+            nodes: vec![Entry {
+                node: Node::Document,
+                span: Span::default(),
+                parent: None,
+                children: Vec::new(),
+            }],
+        };
+        let mut open = vec![res.root()];
+        for (span, component) in doc.flatten().into_components() {
+            let Component::Tag(tag) = component else {
+                res.push(*open.last().unwrap(), Node::Leaf(component), span);
+                continue
+            };
+            match tag.kind() {
+                TagKind::End => {
+                    let Some(depth) = open.iter().rposition(|&id| res.is_open_element(id, tag.tag()))
+                        else {
+                            res.push(*open.last().unwrap(), Node::Leaf(Component::Tag(tag)), span);
+                            continue
+                        };
+                    let id = open[depth];
+                    open.truncate(depth);
+                    let entry = &mut res.nodes[id.0];
+                    entry.span = Span::new(entry.span.start, span.end);
+                    let Node::Element { end, .. } = &mut entry.node else { unreachable!() };
+                    *end = Some(tag);
+                }
+                _ => {
+                    res.close_implied(&mut open, tag.tag());
+                    let opens = tag.opens_element();
+                    let id = res.push(*open.last().unwrap(), Node::Element { start: tag, end: None }, span);
+                    if opens {
+                        open.push(id);
+                    }
+                }
+            }
+        }
+        res
+    }
+    fn close_implied(&self, open: &mut Vec<NodeId>, name: &str) {
+        for (_, ends, stops) in IMPLIED_ENDS.iter().filter(|(starts, _, _)| starts.contains(&name)) {
+            for depth in (1..open.len()).rev() {
+                let Some(tag) = self.element(open[depth]) else { break };
+                if ends.contains(&tag.tag()) {
+                    open.truncate(depth);
+                    break
+                }
+                if stops.contains(&tag.tag()) {
+                    break
+                }
+            }
+        }
+    }
+    fn is_open_element(&self, id: NodeId, name: &str) -> bool {
+        matches!(&self.nodes[id.0].node, Node::Element { start, end: None } if start.tag() == name)
+    }
+    fn push(&mut self, parent: NodeId, node: Node, span: Span) -> NodeId {
+        let id = NodeId(self.nodes.len());
+        self.nodes.push(Entry {
+            node,
+            span,
+            parent: Some(parent),
+            children: Vec::new(),
+        });
+        self.nodes[parent.0].children.push(id);
+        id
+    }
+    pub fn root(&self) -> NodeId {
+        NodeId(0)
+    }
+    pub fn node(&self, id: NodeId) -> &Node {
+        &self.nodes[id.0].node
+    }
+    pub fn node_mut(&mut self, id: NodeId) -> &mut Node {
+        &mut self.nodes[id.0].node
+    }
+    /// The start tag of an element.
+    pub fn element(&self, id: NodeId) -> Option<&Tag> {
+        match &self.nodes[id.0].node {
+            Node::Element { start, .. } => Some(start),
+            _ => None,
+        }
+    }
+    /// The span in the source file, from the start tag of an element to its end tag.
+    pub fn span(&self, id: NodeId) -> Span {
+        self.nodes[id.0].span
+    }
+    pub fn parent(&self, id: NodeId) -> Option<NodeId> {
+        self.nodes[id.0].parent
+    }
+    pub fn children(&self, id: NodeId) -> &[NodeId] {
+        &self.nodes[id.0].children
+    }
+    pub fn next_sibling(&self, id: NodeId) -> Option<NodeId> {
+        let siblings = self.children(self.parent(id)?);
+        let idx = siblings.iter().position(|&sibling| sibling == id)?;
+        siblings.get(idx + 1).copied()
+    }
+    pub fn prev_sibling(&self, id: NodeId) -> Option<NodeId> {
+        let siblings = self.children(self.parent(id)?);
+        let idx = siblings.iter().position(|&sibling| sibling == id)?;
+        idx.checked_sub(1).map(|idx| siblings[idx])
+    }
+    /// Lists the nodes above `id`, from its parent up to the root.
+    pub fn ancestors(&self, id: NodeId) -> Vec<NodeId> {
+        let mut res = Vec::new();
+        let mut next = self.parent(id);
+        while let Some(parent) = next {
+            res.push(parent);
+            next = self.parent(parent);
+        }
+        res
+    }
+    /// Lists the nodes below `id` in document order.
+    pub fn descendants(&self, id: NodeId) -> Vec<NodeId> {
+        let mut res = Vec::new();
+        let mut stack: Vec<_> = self.children(id).iter().rev().copied().collect();
+        while let Some(next) = stack.pop() {
+            res.push(next);
+            stack.extend(self.children(next).iter().rev());
+        }
+        res
+    }
+    /// Finds the elements below `id` matched by a selector, in document order.
+    pub fn find_all(&self, id: NodeId, selector: &Selector) -> Vec<NodeId> {
+        self.descendants(id).into_iter()
+            .filter(|&node| self.element(node).is_some_and(|tag| selector.matches(tag)))
+            .filter(|&node| selector.nth().is_none_or(|nth| self.position_of_type(node) == nth))
+            .collect()
+    }
+    /// Finds the first element below `id` matched by a selector.
+    pub fn find(&self, id: NodeId, selector: &Selector) -> Option<NodeId> {
+        self.find_all(id, selector).first().copied()
+    }
+    /// The position of an element among its siblings with the same tag name, 1-based.
+    fn position_of_type(&self, id: NodeId) -> usize {
+        let (Some(parent), Some(tag)) = (self.parent(id), self.element(id)) else { return 1 };
+        self.children(parent).iter()
+            .take_while(|&&sibling| sibling != id)
+            .filter(|&&sibling| self.element(sibling).is_some_and(|other| other.tag() == tag.tag()))
+            .count() + 1
+    }
+    /// Adds a document as the last child of `parent`.
+    pub fn append(&mut self, parent: NodeId, doc: Doc) -> NodeId {
+        self.insert(parent, self.children(parent).len(), doc)
+    }
+    /// Adds a document as a child of `parent` before the child at `index`.
+    pub fn insert(&mut self, parent: NodeId, index: usize, doc: Doc) -> NodeId {
+        let id = self.push(parent, Node::Leaf(Component::Pointer(doc)), Span::default());
+        let children = &mut self.nodes[parent.0].children;
+        children.pop();
+        children.insert(index.min(children.len()), id);
+        id
+    }
+    /// Removes a node and everything below it from the tree.
+    pub fn detach(&mut self, id: NodeId) {
+        if let Some(parent) = self.nodes[id.0].parent.take() {
+            self.nodes[parent.0].children.retain(|&child| child != id);
+        }
+    }
+    /// Renders a node, e.g. an element with its start tag, its contents and its end tag.
+    pub fn outer(&self, id: NodeId) -> Doc {
+        let mut res = Doc::empty();
+        self.render(id, &mut res);
+        res
+    }
+    /// Renders the children of a node, e.g. the contents of an element.
+    pub fn inner(&self, id: NodeId) -> Doc {
+        let mut res = Doc::empty();
+        for &child in self.children(id) {
+            self.render(child, &mut res);
+        }
+        res
+    }
+    /// Renders the whole tree back into a document.
+    pub fn into_doc(self) -> Doc {
+        self.inner(self.root())
+    }
+    fn render(&self, id: NodeId, dest: &mut Doc) {
+        let entry = &self.nodes[id.0];
+        match &entry.node {
+            Node::Document => {}
+            Node::Element { start, .. } => dest.push(start.span(), Component::Tag(start.clone())),
+            Node::Leaf(component) => dest.push(entry.span, component.clone()),
+        }
+        for &child in &entry.children {
+            self.render(child, dest);
+        }
+        if let Node::Element { end: Some(end), .. } = &entry.node {
+            dest.push(end.span(), Component::Tag(end.clone()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+    use super::*;
+
+    fn build(source: &str) -> Tree {
+        Tree::new(Doc::new(Rc::new(source.to_string()), &mut Vec::new()).unwrap())
+    }
+
+    /// The children of `id`, elements by tag name and everything else by its markup.
+    fn children(tree: &Tree, id: NodeId) -> Vec<String> {
+        tree.children(id).iter()
+            .map(|&child| match tree.element(child) {
+                Some(tag) => tag.tag().to_string(),
+                None => tree.outer(child).to_string(),
+            })
+            .collect()
+    }
+
+    fn find(tree: &Tree, selector: &str) -> NodeId {
+        tree.find(tree.root(), &Selector::parse(selector).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn list_items_end_each_other() {
+        let tree = build("<ul><li>a<li>b</ul>");
+        assert_eq!(children(&tree, find(&tree, "ul")), ["li", "li"]);
+        let tree = build("<li>a<ul><li>b</ul>");
+        assert_eq!(children(&tree, tree.root()), ["li"]);
+        assert_eq!(children(&tree, find(&tree, "ul")), ["li"]);
+    }
+
+    #[test]
+    fn paragraphs_end_at_blocks_and_list_items() {
+        let tree = build("<p>a<div>b</div><p>c<li>d<p>e<dt>f");
+        assert_eq!(children(&tree, tree.root()), ["p", "div", "p", "li"]);
+        assert_eq!(children(&tree, find(&tree, "li")), ["d", "p", "dt"]);
+    }
+
+    #[test]
+    fn paragraphs_do_not_end_across_buttons() {
+        let tree = build("<p>a<button><div>b</div></button></p>");
+        assert_eq!(children(&tree, tree.root()), ["p"]);
+        assert_eq!(children(&tree, find(&tree, "button")), ["div"]);
+    }
+
+    #[test]
+    fn definitions_end_each_other() {
+        let tree = build("<dl><dt>a<dd>b<dt>c</dl>");
+        assert_eq!(children(&tree, find(&tree, "dl")), ["dt", "dd", "dt"]);
+    }
+
+    #[test]
+    fn body_ends_head() {
+        let tree = build("<head><title>t</title><body>x");
+        assert_eq!(children(&tree, tree.root()), ["head", "body"]);
+        assert_eq!(children(&tree, find(&tree, "body")), ["x"]);
+    }
+
+    #[test]
+    fn void_and_self_closing_elements_have_no_children() {
+        let tree = build("<p>a<br>b<img src=x/>c<span/>d</p>");
+        assert_eq!(children(&tree, find(&tree, "p")), ["a", "br", "b", "img", "c", "span", "d"]);
+    }
+
+    #[test]
+    fn end_tags_that_close_nothing_are_leaves() {
+        let tree = build("</div>x<p>y</span></p>");
+        assert_eq!(children(&tree, tree.root()), ["</div>", "x", "p"]);
+        assert_eq!(children(&tree, find(&tree, "p")), ["y", "</span>"]);
+    }
+
+    #[test]
+    fn positions_count_siblings_of_the_same_type() {
+        let tree = build("<section><p>a<div>x</div><p class=c>b</section><section><p class=c>c<p class=c>d</section>");
+        assert_eq!(tree.inner(find(&tree, "p:nth-of-type(2)")).to_string(), "b");
+        assert_eq!(tree.inner(find(&tree, ".c:nth-of-type(1)")).to_string(), "c");
+        assert_eq!(tree.find_all(tree.root(), &Selector::parse("p:nth-of-type(2)").unwrap()).len(), 2);
+        assert_eq!(children(&tree, find(&tree, "section:nth-of-type(2)")), ["p", "p"]);
+        assert_eq!(tree.find(tree.root(), &Selector::parse("p:nth-of-type(3)").unwrap()), None);
+    }
+
+    #[test]
+    fn siblings_and_rendering() {
+        let source = "<!DOCTYPE html>\n<ul><li id=a>1<li>2</ul><script>if (a<b) {}</script>";
+        let tree = build(source);
+        let first = find(&tree, "#a");
+        let second = tree.next_sibling(first).unwrap();
+        assert_eq!(tree.prev_sibling(second), Some(first));
+        assert_eq!(tree.next_sibling(second), None);
+        assert_eq!(tree.inner(second).to_string(), "2");
+        assert_eq!(tree.outer(find(&tree, "ul")).to_string(), "<ul><li id=a>1<li>2</ul>");
+        assert_eq!(tree.into_doc().to_string(), source);
+    }
+}
@@ -0,0 +1,198 @@
+use std::fs;
+use std::io;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::{str, thread};
+use crate::util;
+use crate::util::VecDict;
+
+const RELOAD_PATH: &str = "/__live_reload";
+
+/// Polls the server for the build version and reloads the page when it changes.
+const RELOAD_SCRIPT: &str = r#"<script>(function () {
+  var version = null;
+  function poll() {
+    fetch("/__live_reload", { cache: "no-store" }).then(function (res) { return res.text(); })
+      .then(function (next) {
+        if (version !== null && next !== version) { location.reload(); return; }
+        version = next;
+        setTimeout(poll, 500);
+      }, function () { setTimeout(poll, 1000); });
+  }
+  poll();
+})();</script>"#;
+
+#[derive(Default)]
+struct Status {
+    version: u64,
+    errors: VecDict<PathBuf, String>,
+}
+
+/// The build state shared between the compiler and the server threads.
+#[derive(Clone, Default)]
+pub struct LiveState {
+    inner: Arc<Mutex<Status>>,
+}
+
+impl LiveState {
+    /// Records the result of compiling the page written to `out_path`.
+    pub fn record(&self, out_path: &Path, res: Result<(), &str>) {
+        let out_path = util::normalize_path(out_path);
+        let mut status = self.inner.lock().unwrap();
+        status.errors.remove(&out_path);
+        if let Err(msg) = res {
+            status.errors.push_unique(out_path, msg.to_string());
+        }
+    }
+    /// Tells the connected browsers to reload.
+    pub fn reload(&self) {
+        self.inner.lock().unwrap().version += 1;
+    }
+    fn version(&self) -> u64 {
+        self.inner.lock().unwrap().version
+    }
+    fn error(&self, out_path: &Path) -> Option<String> {
+        self.inner.lock().unwrap().errors.get(out_path).cloned()
+    }
+}
+
+/// Serves the files below `root` on `addr` from a background thread and returns the bound address.
+pub fn start<A: ToSocketAddrs>(addr: A, root: PathBuf, state: LiveState) -> io::Result<SocketAddr> {
+    let listener = TcpListener::bind(addr)?;
+    let local_addr = listener.local_addr()?;
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let root = root.clone();
+            let state = state.clone();
+            thread::spawn(move || {
+                let _ = respond(stream, &root, &state);
+            });
+        }
+    });
+    Ok(local_addr)
+}
+
+fn respond(mut stream: TcpStream, root: &Path, state: &LiveState) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        header.clear();
+    }
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or_default();
+    let target = parts.next().unwrap_or_default();
+    if method != "GET" && method != "HEAD" {
+        return write_response(&mut stream, "405 Method Not Allowed", "text/plain", b"Method Not Allowed", method)
+    }
+    let path = target.split(['?', '#']).next().unwrap_or_default();
+    if path == RELOAD_PATH {
+        let version = state.version().to_string();
+        return write_response(&mut stream, "200 OK", "text/plain", version.as_bytes(), method)
+    }
+    let Some(file) = resolve(root, path) else {
+        return write_response(&mut stream, "404 Not Found", "text/html", &not_found(path), method)
+    };
+    let content_type = content_type(&file);
+    if content_type.starts_with("text/html") {
+        if let Some(msg) = state.error(&util::normalize_path(&file)) {
+            return write_response(&mut stream, "500 Internal Server Error", content_type, &overlay(&msg), method)
+        }
+    }
+    let body = match fs::read(&file) {
+        Ok(body) => body,
+        Err(_) => return write_response(&mut stream, "404 Not Found", "text/html", &not_found(path), method),
+    };
+    if content_type.starts_with("text/html") {
+        let body = inject_script(&String::from_utf8_lossy(&body));
+        return write_response(&mut stream, "200 OK", content_type, body.as_bytes(), method)
+    }
+    write_response(&mut stream, "200 OK", content_type, &body, method)
+}
+
+fn write_response(stream: &mut TcpStream, status: &str, content_type: &str, body: &[u8], method: &str) -> io::Result<()> {
+    write!(stream, "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\n\
+                    Cache-Control: no-store\r\nConnection: close\r\n\r\n",
+           status, content_type, body.len())?;
+    if method != "HEAD" {
+        stream.write_all(body)?;
+    }
+    stream.flush()
+}
+
+/// Maps a request path to a file below `root`, refusing paths that leave it.
+fn resolve(root: &Path, path: &str) -> Option<PathBuf> {
+    let relative = PathBuf::from(percent_decode(path.trim_start_matches('/'))?);
+    if relative.components().any(|c| !matches!(c, Component::Normal(_) | Component::CurDir)) {
+        return None
+    }
+    let file = root.join(relative);
+    if file.is_dir() {
+        return Some(file.join("index.html"))
+    }
+    if !file.exists() && file.extension().is_none() {
+        return Some(file.with_extension("html"))
+    }
+    Some(file)
+}
+
+fn percent_decode(path: &str) -> Option<String> {
+    let bytes = path.as_bytes();
+    let mut res = Vec::with_capacity(bytes.len());
+    let mut idx = 0;
+    while idx < bytes.len() {
+        if bytes[idx] == b'%' {
+            let hex = str::from_utf8(bytes.get(idx + 1..idx + 3)?).ok()?;
+            res.push(u8::from_str_radix(hex, 16).ok()?);
+            idx += 3;
+        } else {
+            res.push(bytes[idx]);
+            idx += 1;
+        }
+    }
+    String::from_utf8(res).ok()
+}
+
+fn content_type(file: &Path) -> &'static str {
+    match file.extension().and_then(|ext| ext.to_str()).unwrap_or_default() {
+        "html" | "htm" => "text/html; charset=utf-8",
+        "css" => "text/css; charset=utf-8",
+        "js" | "mjs" => "text/javascript; charset=utf-8",
+        "json" => "application/json",
+        "txt" => "text/plain; charset=utf-8",
+        "svg" => "image/svg+xml",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "ico" => "image/x-icon",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Inserts the live-reload script before the last `</body>`, or appends it if there is none.
+fn inject_script(html: &str) -> String {
+    let lower = html.to_ascii_lowercase();
+    match lower.rfind("</body>") {
+        Some(idx) => format!("{}{}{}", &html[..idx], RELOAD_SCRIPT, &html[idx..]),
+        None => format!("{}{}", html, RELOAD_SCRIPT),
+    }
+}
+
+fn overlay(msg: &str) -> Vec<u8> {
+    format!("<!DOCTYPE html><html><head><meta charset=\"utf-8\"><title>Compile error</title></head>\
+             <body style=\"margin:0;background:#1e1e1e;color:#f4f4f4;font-family:monospace\">\
+             <div style=\"padding:1.5em\"><h2 style=\"color:#ff6b6b\">Compile error</h2>\
+             <pre style=\"white-space:pre-wrap\">{}</pre></div>{}</body></html>",
+            util::escape_html(msg), RELOAD_SCRIPT).into_bytes()
+}
+
+fn not_found(path: &str) -> Vec<u8> {
+    format!("<!DOCTYPE html><html><body><h2>404 Not Found</h2><p>{}</p>{}</body></html>",
+            util::escape_html(path), RELOAD_SCRIPT).into_bytes()
+}
@@ -0,0 +1,67 @@
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use crate::util;
+
+/// Describes which files below a source directory are top-level pages.
+#[derive(Debug, Clone)]
+pub struct Site {
+    /// The source directory to walk.
+    pub root: PathBuf,
+    /// Glob matched against each file's path relative to `root`, e.g. `**/*.html`.
+    pub pages: String,
+    /// Files and directories whose name starts with this prefix are partials, not pages.
+    /// An empty prefix treats every matched file as a page.
+    pub partial_prefix: String,
+}
+
+impl Site {
+    pub fn new<P: Into<PathBuf>>(root: P) -> Self {
+        Self {
+            root: root.into(),
+            pages: String::from("**/*.html"),
+            partial_prefix: String::from("_"),
+        }
+    }
+    /// Lists every page below `root` in a stable order, skipping `exclude` (e.g. the output directory).
+    pub fn find_pages(&self, exclude: Option<&Path>) -> io::Result<Vec<PathBuf>> {
+        let exclude = exclude.and_then(|path| path.canonicalize().ok());
+        let mut pages = Vec::new();
+        self.walk(&self.root, exclude.as_deref(), &mut pages)?;
+        Ok(pages)
+    }
+    fn walk(&self, dir: &Path, exclude: Option<&Path>, dest: &mut Vec<PathBuf>) -> io::Result<()> {
+        let mut entries = fs::read_dir(dir)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<io::Result<Vec<_>>>()?;
+        entries.sort();
+        for path in entries {
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            if name.starts_with('.') {
+                continue
+            }
+            if path.is_dir() {
+                if exclude.is_some() && path.canonicalize().ok().as_deref() == exclude {
+                    continue
+                }
+                self.walk(&path, exclude, dest)?;
+            } else if self.is_page(&path) {
+                dest.push(path);
+            }
+        }
+        Ok(())
+    }
+    fn is_page(&self, path: &Path) -> bool {
+        let Ok(relative) = path.strip_prefix(&self.root) else { return false };
+        let mut parts = Vec::new();
+        for component in relative.components() {
+            let Component::Normal(part) = component else { continue };
+            let part = part.to_string_lossy();
+            if !self.partial_prefix.is_empty() && part.starts_with(&self.partial_prefix) {
+                return false
+            }
+            parts.push(part);
+        }
+        util::matches_glob(&self.pages, &parts.join("/"))
+    }
+}
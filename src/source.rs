@@ -1,77 +1,75 @@
+/// A cursor over the bytes of a source. `from` is the position of the cursor;
+/// `next_at_first_of` marks the range up to a delimiter.
 pub struct SourceManager<'a> {
     source: &'a [u8],
     from: usize,
     end: usize,
-    next_from: usize,
 }
 
 impl<'a> SourceManager<'a> {
-    pub fn new(source: &'a String) -> Self {
+    pub fn new<S: AsRef<[u8]> + ?Sized>(source: &'a S) -> Self {
+        let source = source.as_ref();
         Self {
-            source: source.as_bytes(),
+            source,
             from: 0,
-            end: 0,
-            next_from: 0,
+            end: source.len(),
         }
     }
+    /// Marks the range from the cursor up to the first `bytes`. Returns false if there is none,
+    /// in which case the range extends to the end of the source.
     pub fn next_at_first_of(&mut self, bytes: &[u8]) -> bool {
-        let len = bytes.len();
-        let to = self.end - len;
-        for i in self.from..=to {
-            if &self.source[i..i + len] == bytes {
-                self.end = i;
-                self.next_from = i + len;
-                return true
-            }
-        }
-        false
+        let found = self.find(bytes);
+        self.end = found.unwrap_or(self.source.len());
+        found.is_some()
     }
-    pub fn next_at_first_not_of(&mut self, bytes: &[u8]) -> bool {
-        let len = bytes.len();
-        let to = self.end - len;
-        for i in self.from..=to {
-            if &self.source[i..i + len] != bytes {
-                self.end = i;
-                self.next_from = i + len;
-                return true
-            }
+    pub fn pop_if_starts_with(&mut self, bytes: &[u8]) -> bool {
+        if !self.starts_with(bytes) {
+            return false
         }
-        false
+        self.from += bytes.len();
+        true
     }
-    pub fn move_to_next(&mut self) {
-        self.from = self.next_from;
-        self.end = self.source.len();
-        self.next_from = self.source.len();
+    pub fn starts_with(&self, bytes: &[u8]) -> bool {
+        self.rest().starts_with(bytes)
     }
-    pub fn is_empty(&self) -> bool {
-        self.from == self.source.len()
+    /// The position of the cursor in the source.
+    pub fn offset(&self) -> usize {
+        self.from
     }
-    fn validate_index(&self, idx: usize) {
-        if idx < self.from || idx > self.end {
-            panic!("SourceManager: byte index {idx} is out of bounds")
-        }
+    /// The end of the range marked by the last `next_at_first_of`.
+    pub fn partial_end(&self) -> usize {
+        self.end
     }
-    pub fn pop_if_starts_with(&mut self, bytes: &[u8]) -> bool {
-        let len = bytes.len();
-        if self.end - self.from < len {
-            return false
-        }
-        if &self.source[self.from..self.from + len] != bytes {
-            return false
-        }
-        self.from += len;
-        true
+    pub fn peek(&self) -> Option<u8> {
+        self.peek_at(0)
     }
-    pub fn partially_to_vec(&self) -> Vec<u8> {
-        self.source[self.from..self.end].to_vec()
+    pub fn peek_at(&self, n: usize) -> Option<u8> {
+        self.source.get(self.from + n).copied()
     }
-    pub fn partially_from(&self) -> Self {
-        let source = &self.source[self.from..self.end];
-        Self {
-            source,
-            from: 0,
-            end: source.len(),
-            next_from: source.len(),
+    pub fn advance(&mut self, n: usize) {
+        self.from = (self.from + n).min(self.source.len());
+    }
+    /// Moves the cursor past the bytes matching `pred`, returning how many there were.
+    pub fn skip_while<F: Fn(u8) -> bool>(&mut self, pred: F) -> usize {
+        let n = self.rest().iter().take_while(|b| pred(**b)).count();
+        self.from += n;
+        n
+    }
+    /// Finds the first `bytes` at or after the cursor.
+    pub fn find(&self, bytes: &[u8]) -> Option<usize> {
+        if bytes.is_empty() {
+            return Some(self.from)
         }
+        self.rest().windows(bytes.len())
+            .position(|window| window == bytes)
+            .map(|i| self.from + i)
+    }
+    /// The whole source, regardless of the cursor.
+    pub fn source(&self) -> &'a [u8] {
+        self.source
+    }
+    /// The bytes from the cursor to the end of the source.
+    pub fn rest(&self) -> &'a [u8] {
+        &self.source[self.from..]
     }
 }
@@ -1,10 +1,11 @@
-use std::{io, mem};
+use std::io;
+use std::path::{Component, Path, PathBuf};
 use std::io::{BufRead, BufReader};
 
 pub fn first_of(slice: &[u8], target: u8, from: usize) -> usize {
     let len = slice.len();
-    for i in from..len {
-        if slice[i] == target {
+    for (i, byte) in slice.iter().enumerate().skip(from) {
+        if *byte == target {
             return i
         }
     }
@@ -13,8 +14,8 @@ pub fn first_of(slice: &[u8], target: u8, from: usize) -> usize {
 
 pub fn first_not_of(slice: &[u8], target: u8, from: usize) -> usize {
     let len = slice.len();
-    for i in from..len {
-        if slice[i] != target {
+    for (i, byte) in slice.iter().enumerate().skip(from) {
+        if *byte != target {
             return i
         }
     }
@@ -54,17 +55,23 @@ impl<K, T> LazyStatus<K, T> {
     fn init(&mut self) {
         let Self::Uninitialized(func, key) = self
             else { return };
-        let key = mem::replace(key, None).unwrap();
+        let key = key.take().unwrap();
         let inner = func(key);
         *self = Self::Initialized(inner);
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct VecDict<K: PartialEq, V> {
     dict: Vec<(K, V)>,
 }
 
+impl<K: PartialEq, V> Default for VecDict<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<K: PartialEq, V> VecDict<K, V> {
     pub fn new() -> Self {
         Self {
@@ -103,6 +110,24 @@ impl<K: PartialEq, V> VecDict<K, V> {
         }
         None
     }
+    pub fn remove<T: PartialEq<K> + ?Sized>(&mut self, key: &T) -> Option<V> {
+        let idx = self.dict.iter().position(|(k, _)| *key == *k)?;
+        Some(self.dict.remove(idx).1)
+    }
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        self.dict.iter().map(|(k, v)| (k, v))
+    }
+    pub fn len(&self) -> usize {
+        self.dict.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.dict.is_empty()
+    }
+    pub fn for_each_mut<F: FnMut(&K, &mut V)>(&mut self, mut func: F) {
+        for (k, v) in self.dict.iter_mut() {
+            func(k, v);
+        }
+    }
     pub fn for_each<F: FnMut(&K, &V)>(&self, mut func: F) {
         let len = self.dict.len();
         for i in 0..len {
@@ -111,3 +136,111 @@ impl<K: PartialEq, V> VecDict<K, V> {
         }
     }
 }
+
+impl<K: PartialEq, V> IntoIterator for VecDict<K, V> {
+    type Item = (K, V);
+    type IntoIter = std::vec::IntoIter<(K, V)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.dict.into_iter()
+    }
+}
+
+/// Matches a `/`-separated path against a glob.
+/// `*` and `?` do not cross `/`, while `**` matches any number of directories.
+pub fn matches_glob(pattern: &str, path: &str) -> bool {
+    glob(pattern.as_bytes(), path.as_bytes())
+}
+
+fn glob(pattern: &[u8], path: &[u8]) -> bool {
+    match pattern {
+        [] => path.is_empty(),
+        [b'*', b'*', b'/', rest @ ..] => {
+            glob(rest, path) || (0..path.len())
+                .any(|i| path[i] == b'/' && glob(rest, &path[i + 1..]))
+        }
+        [b'*', b'*', rest @ ..] => (0..=path.len()).any(|i| glob(rest, &path[i..])),
+        [b'*', rest @ ..] => {
+            let end = first_of(path, b'/', 0);
+            (0..=end).any(|i| glob(rest, &path[i..]))
+        }
+        [b'?', rest @ ..] => !path.is_empty() && path[0] != b'/' && glob(rest, &path[1..]),
+        [c, rest @ ..] => path.first() == Some(c) && glob(rest, &path[1..]),
+    }
+}
+
+/// Removes `.` and resolves `..` lexically, so that one file is cached under one key.
+pub fn normalize_path(path: &Path) -> PathBuf {
+    let mut res = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir if matches!(res.components().next_back(), Some(Component::Normal(_))) => {
+                res.pop();
+            }
+            _ => res.push(component),
+        }
+    }
+    res
+}
+
+pub fn escape_html(text: &str) -> String {
+    let mut res = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => res.push_str("&amp;"),
+            '<' => res.push_str("&lt;"),
+            '>' => res.push_str("&gt;"),
+            '"' => res.push_str("&quot;"),
+            '\'' => res.push_str("&#39;"),
+            _ => res.push(c),
+        }
+    }
+    res
+}
+
+/// Removes one pair of matching quotes around an attribute value.
+pub fn unquote(value: &str) -> &str {
+    for quote in ['"', '\''] {
+        if value.len() >= 2 && value.starts_with(quote) && value.ends_with(quote) {
+            return &value[1..value.len() - 1]
+        }
+    }
+    value
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn single_stars_stay_in_one_directory() {
+        assert!(matches_glob("*.html", "index.html"));
+        assert!(!matches_glob("*.html", "posts/a.html"));
+        assert!(matches_glob("posts/*.html", "posts/a.html"));
+        assert!(!matches_glob("posts/*.html", "posts/2024/a.html"));
+        assert!(matches_glob("a?c.html", "abc.html"));
+        assert!(!matches_glob("a?c.html", "a/c.html"));
+    }
+
+    #[test]
+    fn double_stars_cross_directories() {
+        assert!(matches_glob("**/*.html", "index.html"));
+        assert!(matches_glob("**/*.html", "posts/2024/a.html"));
+        assert!(!matches_glob("**/*.html", "posts/a.css"));
+        assert!(matches_glob("posts/**/*.html", "posts/a.html"));
+        assert!(matches_glob("posts/**/*.html", "posts/2024/01/a.html"));
+        assert!(!matches_glob("posts/**/*.html", "drafts/a.html"));
+        assert!(matches_glob("**", "any/path.txt"));
+    }
+
+    #[test]
+    fn paths_are_normalized_lexically() {
+        assert_eq!(normalize_path(Path::new("a/./b/../c.html")), Path::new("a/c.html"));
+        assert_eq!(normalize_path(Path::new("./a.html")), Path::new("a.html"));
+        assert_eq!(normalize_path(Path::new("a/../../b.html")), Path::new("../b.html"));
+        assert_eq!(normalize_path(Path::new("../../b.html")), Path::new("../../b.html"));
+        assert_eq!(normalize_path(Path::new("/site/a/../b.html")), Path::new("/site/b.html"));
+        assert_eq!(normalize_path(Path::new("")), Path::new(""));
+    }
+}
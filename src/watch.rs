@@ -0,0 +1,117 @@
+use std::ffi::{CString, OsStr};
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::io::RawFd;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use crate::util;
+use crate::util::VecDict;
+
+/// Events that mean a file in a watched directory may have new content.
+/// Editors often save by writing a temporary file and renaming it over the original,
+/// so the directories are watched instead of the files themselves.
+const MASK: u32 = libc::IN_CLOSE_WRITE | libc::IN_MOVED_TO | libc::IN_CREATE
+    | libc::IN_DELETE | libc::IN_MOVED_FROM | libc::IN_MODIFY;
+
+pub struct Watcher {
+    fd: RawFd,
+    dirs: VecDict<libc::c_int, PathBuf>,
+}
+
+impl Watcher {
+    pub fn new() -> io::Result<Self> {
+        let fd = unsafe { libc::inotify_init1(libc::IN_CLOEXEC) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error())
+        }
+        Ok(Self {
+            fd,
+            dirs: VecDict::new(),
+        })
+    }
+    /// Watches the directory containing `file`. Watching the same directory twice has no effect.
+    pub fn watch_file(&mut self, file: &Path) -> io::Result<()> {
+        match file.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => self.watch_dir(dir),
+            _ => self.watch_dir(Path::new(".")),
+        }
+    }
+    pub fn watch_dir(&mut self, dir: &Path) -> io::Result<()> {
+        let dir = util::normalize_path(dir);
+        let dir = if dir.as_os_str().is_empty() { PathBuf::from(".") } else { dir };
+        let mut known = false;
+        self.dirs.for_each(|_, path| known |= *path == dir);
+        if known {
+            return Ok(())
+        }
+        let c_path = CString::new(dir.as_os_str().as_bytes())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let wd = unsafe { libc::inotify_add_watch(self.fd, c_path.as_ptr(), MASK) };
+        if wd < 0 {
+            return Err(io::Error::last_os_error())
+        }
+        self.dirs.push_unique(wd, dir);
+        Ok(())
+    }
+    /// Blocks until a file in a watched directory changes, then keeps collecting events
+    /// until none arrive for `quiet`, so that a burst of events from one save is reported once.
+    pub fn wait(&mut self, quiet: Duration) -> io::Result<Vec<PathBuf>> {
+        let mut changed = Vec::new();
+        self.poll(None)?;
+        self.read_events(&mut changed)?;
+        while self.poll(Some(quiet))? {
+            self.read_events(&mut changed)?;
+        }
+        Ok(changed)
+    }
+    fn poll(&self, timeout: Option<Duration>) -> io::Result<bool> {
+        let mut fds = libc::pollfd {
+            fd: self.fd,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let timeout = timeout.map_or(-1, |t| t.as_millis().min(i32::MAX as u128) as libc::c_int);
+        loop {
+            let res = unsafe { libc::poll(&mut fds, 1, timeout) };
+            if res >= 0 {
+                return Ok(res > 0)
+            }
+            let err = io::Error::last_os_error();
+            if err.kind() != io::ErrorKind::Interrupted {
+                return Err(err)
+            }
+        }
+    }
+    fn read_events(&mut self, dest: &mut Vec<PathBuf>) -> io::Result<()> {
+        let mut buf = [0u8; 4096];
+        let len = unsafe { libc::read(self.fd, buf.as_mut_ptr().cast(), buf.len()) };
+        if len < 0 {
+            return Err(io::Error::last_os_error())
+        }
+        let header = std::mem::size_of::<libc::inotify_event>();
+        let mut idx = 0;
+        while idx + header <= len as usize {
+            let event = unsafe {
+                std::ptr::read_unaligned(buf[idx..].as_ptr().cast::<libc::inotify_event>())
+            };
+            let name = &buf[idx + header..idx + header + event.len as usize];
+            let name = &name[..util::first_of(name, 0, 0)];
+            if event.mask & libc::IN_IGNORED != 0 {
+                self.dirs.remove(&event.wd);
+            } else if let Some(dir) = self.dirs.get(&event.wd) {
+                let path = util::normalize_path(&dir.join(OsStr::from_bytes(name)));
+                if !dest.contains(&path) {
+                    dest.push(path);
+                }
+            }
+            idx += header + event.len as usize;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Watcher {
+    fn drop(&mut self) {
+        unsafe { libc::close(self.fd) };
+    }
+}